@@ -0,0 +1,3 @@
+mod projectile_bundle;
+
+pub use projectile_bundle::ProjectileBundle;
@@ -0,0 +1,21 @@
+use rust_ecs::{derive::Bundle, Transform};
+
+use crate::components::{ProjectileComponent, VelocityComponent};
+
+// The components that differ between projectiles, added to instances of the "projectile" prefab.
+#[derive(Bundle)]
+pub struct ProjectileBundle {
+    pub transform: Transform,
+    pub velocity: VelocityComponent,
+    pub projectile: ProjectileComponent,
+}
+
+impl ProjectileBundle {
+    pub fn new(
+        transform: Transform,
+        velocity: VelocityComponent,
+        projectile: ProjectileComponent,
+    ) -> Self {
+        Self { transform, velocity, projectile }
+    }
+}
@@ -1,7 +1,7 @@
 use macroquad::math::Vec2;
 use rust_ecs::derive::Component;
 
-#[derive(Component, Debug)]
+#[derive(Component, Clone, Debug)]
 pub struct Box2dColliderComponent {
     pub offset: Vec2,
     pub size: Vec2,
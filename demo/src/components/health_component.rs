@@ -1,6 +1,6 @@
 use rust_ecs::derive::Component;
 
-#[derive(Component, Debug)]
+#[derive(Component, Clone, Debug)]
 pub struct HealthComponent {
     pub health: u32,
 }
@@ -6,7 +6,6 @@ mod keyboard_control_component;
 mod projectile_component;
 mod projectile_emitter_component;
 mod sprite_component;
-mod transform_component;
 mod velocity_component;
 
 pub use animation_component::AnimationComponent;
@@ -17,5 +16,4 @@ pub use keyboard_control_component::KeyboardControlComponent;
 pub use projectile_component::ProjectileComponent;
 pub use projectile_emitter_component::ProjectileEmitterComponent;
 pub use sprite_component::SpriteComponent;
-pub use transform_component::TransformComponent;
 pub use velocity_component::VelocityComponent;
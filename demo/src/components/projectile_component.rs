@@ -6,5 +6,4 @@ pub struct ProjectileComponent {
     pub max_duration: Duration,
     pub created: SystemTime,
     pub damage: u32,
-    pub friendly: bool,
 }
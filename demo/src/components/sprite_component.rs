@@ -1,6 +1,6 @@
 use macroquad::prelude::*;
 
-#[derive(rust_ecs::derive::Component, Debug)]
+#[derive(rust_ecs::derive::Component, Clone, Debug)]
 pub struct SpriteComponent {
     pub sprite_name: String,
     pub src_rect: Option<Rect>,
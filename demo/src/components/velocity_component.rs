@@ -1,5 +1,5 @@
 use macroquad::prelude::*;
 
 // A velocity component, with the entity position.
-#[derive(rust_ecs::derive::Component, Debug)]
+#[derive(rust_ecs::derive::Component, Clone, Debug)]
 pub struct VelocityComponent(pub Vec2);
@@ -1,5 +1,7 @@
+mod bundles;
 mod components;
 mod events;
+mod relations;
 mod resources;
 mod systems;
 mod tilemap;
@@ -14,20 +16,70 @@ use std::{
 
 use components::{
     AnimationComponent, Box2dColliderComponent, CameraFollowComponent, HealthComponent,
-    KeyboardControlComponent, SpriteComponent, TransformComponent, VelocityComponent,
+    KeyboardControlComponent, SpriteComponent, VelocityComponent,
 };
 use events::KeyboardEvent;
 use macroquad::prelude::*;
 
 use crate::components::ProjectileEmitterComponent;
 use resources::{Camera, MapDimensions};
-use rust_ecs::{events::EventBus, EntityComponentSystem, EntityManager};
+use rust_ecs::{
+    events::{DispatchMode, EventBus},
+    EntityComponentSystem, EntityManager, Prefab, Transform, TransformPropagationSystem,
+};
 use tilemap::load_map;
 
 fn window_conf() -> Conf {
     Conf { window_title: "Demo".to_string(), ..Default::default() }
 }
 
+// The components shared by the enemy vehicles.
+fn enemy_prefab(sprite_name: &str) -> Prefab {
+    Prefab::new()
+        .with(Box2dColliderComponent { offset: Vec2::new(0.0, 0.0), size: Vec2::new(32.0, 32.0) })
+        .with(Transform::default())
+        .with(VelocityComponent(Vec2::new(0.0, 0.0)))
+        .with(SpriteComponent::new(sprite_name, Vec2::new(32.0, 32.0)).with_z_index(1))
+        .with(HealthComponent { health: 100 })
+        .group("enemy")
+}
+
+fn register_prefabs(ecs: &mut EntityComponentSystem) {
+    ecs.register_prefab(
+        "projectile",
+        Prefab::new()
+            .with(Box2dColliderComponent { offset: Vec2::new(0.0, 0.0), size: Vec2::new(4.0, 4.0) })
+            .with(SpriteComponent::new("bullet", Vec2::new(4.0, 4.0)).with_z_index(4))
+            .group("projectile"),
+    );
+
+    // The tank's cannon is a child entity, emitting projectiles from the front of the tank.
+    ecs.register_prefab("tank", enemy_prefab("tank").child("tank_cannon"));
+    ecs.register_prefab(
+        "tank_cannon",
+        Prefab::new()
+            .with(Transform::from_translation(Vec2::new(32.0, 16.0)))
+            .with(ProjectileEmitterComponent {
+                repeat_interval: Some(Duration::from_secs(1)),
+                projectile_velocity: Vec2::new(150.0, 0.0),
+                last_emitted: SystemTime::now(),
+                projectile_duration: Duration::from_secs(5),
+                damage: 10,
+            }),
+    );
+
+    ecs.register_prefab(
+        "truck",
+        enemy_prefab("truck").with(ProjectileEmitterComponent {
+            repeat_interval: Some(Duration::from_secs(3)),
+            projectile_velocity: Vec2::new(0.0, 150.0),
+            last_emitted: SystemTime::now(),
+            projectile_duration: Duration::from_secs(5),
+            damage: 10,
+        }),
+    );
+}
+
 pub async fn setup(ecs: &mut EntityComponentSystem) {
     // Load assets.
     ecs.asset_manager_mut()
@@ -55,7 +107,13 @@ pub async fn setup(ecs: &mut EntityComponentSystem) {
         .await
         .unwrap();
 
+    // Listeners receive the events between systems, so they can emit events of their own.
+    ecs.event_bus_cloned()
+        .borrow_mut()
+        .set_dispatch_mode(DispatchMode::Queued);
+
     // Combining Component queries with system functions, we can add systems like this:
+    ecs.add_system(TransformPropagationSystem::default());
     ecs.add_system(systems::RenderSystem::default());
     ecs.add_system(systems::CollisionSystem::default());
     ecs.add_system(systems::MovementSystem::default());
@@ -77,105 +135,60 @@ pub async fn setup(ecs: &mut EntityComponentSystem) {
         let tile_src_y = (tile.sprite_id / 10 * 32) as f32;
         let tile_src_x = (tile.sprite_id % 10 * 32) as f32;
 
-        let entity = ecs.create_entity();
-        ecs.entity_manager().add_entity_to_group(&entity, "tile");
-        ecs.add_component(entity, TransformComponent(Vec2::new(tile_x, tile_y)));
-        ecs.add_component(
-            entity,
-            SpriteComponent::new(
-                "jungle",
-                Vec2::new(32.0 * tile_scale as f32, 32.0 * tile_scale as f32),
+        ecs.spawn()
+            .with(Transform::from_translation(Vec2::new(tile_x, tile_y)))
+            .with(
+                SpriteComponent::new(
+                    "jungle",
+                    Vec2::new(32.0 * tile_scale as f32, 32.0 * tile_scale as f32),
+                )
+                .with_src_rect(Rect::new(
+                    tile_src_x + 0.5,
+                    tile_src_y + 0.5,
+                    31.0,
+                    31.0,
+                )),
             )
-            .with_src_rect(Rect::new(tile_src_x + 0.5, tile_src_y + 0.5, 31.0, 31.0)),
-        )
+            .group("tile")
+            .id();
     }
 
-    // Create entities with components.
-    let tank = ecs.create_entity();
-    ecs.entity_manager().add_entity_to_group(&tank, "enemy");
-    ecs.add_component(
-        tank,
-        Box2dColliderComponent { offset: Vec2::new(0.0, 0.0), size: Vec2::new(32.0, 32.0) },
-    );
-    ecs.add_component(tank, TransformComponent(glam::Vec2::ZERO));
-    ecs.add_component(tank, VelocityComponent(Vec2::new(0.0, 0.0)));
-    ecs.add_component(
-        tank,
-        SpriteComponent::new("tank", Vec2::new(32.0, 32.0)).with_z_index(1),
-    );
-    ecs.add_component(
-        tank,
-        ProjectileEmitterComponent {
-            repeat_interval: Some(Duration::from_secs(1)),
-            projectile_velocity: Vec2::new(150.0, 0.0),
-            last_emitted: SystemTime::now(),
-            projectile_duration: Duration::from_secs(5),
-            damage: 10,
-            friendly: false,
-        },
-    );
-    ecs.add_component(tank, HealthComponent { health: 100 });
+    register_prefabs(ecs);
 
-    let truck = ecs.create_entity();
-    ecs.entity_manager().add_entity_to_group(&truck, "enemy");
-    ecs.add_component(
-        truck,
-        Box2dColliderComponent { offset: Vec2::new(0.0, 0.0), size: Vec2::new(32.0, 32.0) },
-    );
-    ecs.add_component(truck, TransformComponent(Vec2::new(100.0, 0.0)));
-    ecs.add_component(truck, VelocityComponent(Vec2::new(-0.0, 0.0)));
-    ecs.add_component(
-        truck,
-        SpriteComponent::new("truck", Vec2::new(32.0, 32.0)).with_z_index(1),
-    );
-    ecs.add_component(
-        truck,
-        ProjectileEmitterComponent {
-            repeat_interval: Some(Duration::from_secs(3)),
-            projectile_velocity: Vec2::new(0.0, 150.0),
-            last_emitted: SystemTime::now(),
-            projectile_duration: Duration::from_secs(5),
-            damage: 10,
-            friendly: false,
-        },
-    );
-    ecs.add_component(truck, HealthComponent { health: 100 });
+    // Create entities from prefabs, and with components.
+    ecs.instantiate("tank").unwrap().id();
+    ecs.instantiate("truck")
+        .unwrap()
+        .with(Transform::from_translation(Vec2::new(100.0, 0.0)))
+        .id();
 
-    let chopper = ecs.create_entity();
-    ecs.entity_manager().set_tag(chopper, "player");
-    ecs.add_component(
-        chopper,
-        Box2dColliderComponent { offset: Vec2::new(0.0, 0.0), size: Vec2::new(32.0, 32.0) },
-    );
-    ecs.add_component(chopper, TransformComponent(Vec2::new(0.0, 100.0)));
-    ecs.add_component(chopper, VelocityComponent(Vec2::new(0.0, 0.0)));
-    ecs.add_component(chopper, KeyboardControlComponent(100.0));
-    ecs.add_component(
-        chopper,
-        SpriteComponent::new("chopper", Vec2::new(32.0, 32.0))
-            .with_z_index(1)
-            .with_src_rect(Rect::new(0.0, 0.0, 32.0, 32.0)),
-    );
-    ecs.add_component(
-        chopper,
-        AnimationComponent::new()
-            .num_frames(2)
-            .framerate(15)
-            .is_loop(true),
-    );
-    ecs.add_component(chopper, CameraFollowComponent);
-    ecs.add_component(
-        chopper,
-        ProjectileEmitterComponent {
+    ecs.spawn()
+        .with(Box2dColliderComponent { offset: Vec2::new(0.0, 0.0), size: Vec2::new(32.0, 32.0) })
+        .with(Transform::from_translation(Vec2::new(0.0, 100.0)))
+        .with(VelocityComponent(Vec2::new(0.0, 0.0)))
+        .with(KeyboardControlComponent(100.0))
+        .with(
+            SpriteComponent::new("chopper", Vec2::new(32.0, 32.0))
+                .with_z_index(1)
+                .with_src_rect(Rect::new(0.0, 0.0, 32.0, 32.0)),
+        )
+        .with(
+            AnimationComponent::new()
+                .num_frames(2)
+                .framerate(15)
+                .is_loop(true),
+        )
+        .with(CameraFollowComponent)
+        .with(ProjectileEmitterComponent {
             repeat_interval: None,
             projectile_velocity: Vec2::new(150.0, 150.0),
             last_emitted: SystemTime::now(),
             projectile_duration: Duration::from_secs(5),
             damage: 10,
-            friendly: true,
-        },
-    );
-    ecs.add_component(chopper, HealthComponent { health: 100 });
+        })
+        .with(HealthComponent { health: 100 })
+        .tag("player")
+        .id();
 
     tracing::info!("Added Entities");
 
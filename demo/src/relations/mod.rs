@@ -0,0 +1,8 @@
+use rust_ecs::{Relation, RelationCleanup};
+
+// Relates a projectile to the entity that fired it. Projectiles disappear along with their owner.
+pub struct OwnedBy;
+
+impl Relation for OwnedBy {
+    const ON_TARGET_DESPAWN: RelationCleanup = RelationCleanup::DespawnSources;
+}
@@ -40,37 +40,31 @@ impl System for AnimationSystem {
         _event_bus: std::rc::Rc<std::cell::RefCell<rust_ecs::events::EventBus>>,
         _resources: std::rc::Rc<std::cell::RefCell<rust_ecs::Resources>>,
     ) {
-        for entity in &self.entities {
-            let sprite = entity_manager
-                .get_component::<SpriteComponent>(entity)
-                .unwrap();
-            let animation = entity_manager
-                .get_component::<AnimationComponent>(entity)
-                .unwrap();
-            let time = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis() as usize;
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as usize;
 
-            let mut sprite = sprite.borrow_mut();
-            let mut animation = animation.borrow_mut();
+        entity_manager
+            .query::<(&mut SpriteComponent, &mut AnimationComponent)>()
+            .for_each(|_, (mut sprite, mut animation)| {
+                animation.current_frame = ((time - animation.start_time) * animation.framerate
+                    / 1000)
+                    % animation.num_frames;
 
-            animation.current_frame =
-                ((time - animation.start_time) * animation.framerate / 1000) % animation.num_frames;
+                let src_y = if let Some(src_rect) = sprite.src_rect {
+                    src_rect.y
+                } else {
+                    0.0
+                };
 
-            let src_y = if let Some(src_rect) = sprite.src_rect {
-                src_rect.y
-            } else {
-                0.0
-            };
-
-            sprite.src_rect = Some(Rect::new(
-                animation.current_frame as f32 * sprite.dst_size.x,
-                src_y,
-                sprite.dst_size.x,
-                sprite.dst_size.y,
-            ));
-        }
+                sprite.src_rect = Some(Rect::new(
+                    animation.current_frame as f32 * sprite.dst_size.x,
+                    src_y,
+                    sprite.dst_size.x,
+                    sprite.dst_size.y,
+                ));
+            });
     }
 }
 
@@ -1,10 +1,12 @@
 use std::collections::HashSet;
 
 use macroquad::math::Rect;
-use rust_ecs::{events::EventListener, systems::System, ComponentSignature, Entity};
+use rust_ecs::{
+    events::EventListener, systems::System, ComponentSignature, Entity, GlobalTransform,
+};
 
 use crate::{
-    components::{CameraFollowComponent, TransformComponent},
+    components::CameraFollowComponent,
     resources::{Camera, MapDimensions},
 };
 
@@ -17,7 +19,7 @@ impl Default for CameraFollowSystem {
     fn default() -> Self {
         let mut signature = ComponentSignature::default();
         signature.require_component::<CameraFollowComponent>();
-        signature.require_component::<TransformComponent>();
+        signature.require_component::<GlobalTransform>();
         Self { signature, entities: Default::default() }
     }
 }
@@ -45,26 +47,26 @@ impl System for CameraFollowSystem {
         _event_bus: std::rc::Rc<std::cell::RefCell<rust_ecs::events::EventBus>>,
         resources: std::rc::Rc<std::cell::RefCell<rust_ecs::Resources>>,
     ) {
-        let mut res = resources.borrow_mut();
+        let res = resources.borrow();
 
         let map_dimensions = res.get::<MapDimensions>().unwrap().0;
-        let camera = res.get_mut::<Camera>().unwrap();
+        let mut camera = res.get_mut::<Camera>().unwrap();
 
         let Some(entity) = self.entities.iter().next() else {
             return;
         };
 
         let transform = entity_manager
-            .get_component::<TransformComponent>(entity)
+            .get_component::<GlobalTransform>(entity)
             .unwrap();
 
-        let transform = transform.borrow();
+        let position = transform.borrow().translation();
 
         camera.0 = {
-            let camera_left = (transform.0.x - camera.0.w / 2.0)
+            let camera_left = (position.x - camera.0.w / 2.0)
                 .max(0.0)
                 .min(map_dimensions.x);
-            let camera_top = (transform.0.y - camera.0.h / 2.0)
+            let camera_top = (position.y - camera.0.h / 2.0)
                 .max(0.0)
                 .min(map_dimensions.y);
             Rect::new(camera_left, camera_top, camera.0.w, camera.0.h)
@@ -7,13 +7,10 @@ use std::{
 use rust_ecs::{
     events::{EventBus, EventListener},
     systems::System,
-    ComponentSignature, Entity, EntityManager,
+    ComponentSignature, Entity, EntityManager, GlobalTransform,
 };
 
-use crate::{
-    components::{Box2dColliderComponent, TransformComponent},
-    events::CollisionEvent,
-};
+use crate::{components::Box2dColliderComponent, events::CollisionEvent};
 
 pub struct CollisionSystem {
     signature: ComponentSignature,
@@ -23,7 +20,7 @@ pub struct CollisionSystem {
 impl Default for CollisionSystem {
     fn default() -> Self {
         let mut signature = ComponentSignature::default();
-        signature.require_component::<TransformComponent>();
+        signature.require_component::<GlobalTransform>();
         signature.require_component::<Box2dColliderComponent>();
         Self { signature, entities: Default::default() }
     }
@@ -52,45 +49,23 @@ impl System for CollisionSystem {
         event_bus: Rc<RefCell<EventBus>>,
         _resources: std::rc::Rc<std::cell::RefCell<rust_ecs::Resources>>,
     ) {
-        let entities = self.entities.iter().collect::<Vec<_>>();
-        for (i, entity_a) in entities.iter().enumerate() {
-            for entity_b in &entities[i + 1..] {
-                let collided = {
-                    let transform_a = em
-                        .get_component::<TransformComponent>(entity_a)
-                        .unwrap();
-                    let box_a = em
-                        .get_component::<Box2dColliderComponent>(entity_a)
-                        .unwrap();
-                    let transform_b = em
-                        .get_component::<TransformComponent>(entity_b)
-                        .unwrap();
-                    let box_b = em
-                        .get_component::<Box2dColliderComponent>(entity_b)
-                        .unwrap();
-
-                    let transform_a = transform_a.borrow();
-                    let box_a = box_a.borrow();
-                    let transform_b = transform_b.borrow();
-                    let box_b = box_b.borrow();
-
-                    let a = transform_a.0;
-                    let b = transform_b.0;
-                    let a_width = box_a.size.x;
-                    let a_height = box_a.size.y;
-                    let b_width = box_b.size.x;
-                    let b_height = box_b.size.y;
+        let mut colliders = Vec::new();
+        em.query::<(&GlobalTransform, &Box2dColliderComponent)>()
+            .for_each(|entity, (transform, collider)| {
+                colliders.push((entity, transform.translation(), collider.size));
+            });
 
-                    a.x < b.x + b_width
-                        && a.x + a_width > b.x
-                        && a.y < b.y + b_height
-                        && a.y + a_height > b.y
-                };
+        for (i, (entity_a, a, a_size)) in colliders.iter().enumerate() {
+            for (entity_b, b, b_size) in &colliders[i + 1..] {
+                let collided = a.x < b.x + b_size.x
+                    && a.x + a_size.x > b.x
+                    && a.y < b.y + b_size.y
+                    && a.y + a_size.y > b.y;
 
                 if collided {
-                    event_bus.borrow_mut().emit(
+                    event_bus.borrow().emit(
                         em.clone(),
-                        CollisionEvent { entity_a: **entity_a, entity_b: **entity_b },
+                        CollisionEvent { entity_a: *entity_a, entity_b: *entity_b },
                     );
                 }
             }
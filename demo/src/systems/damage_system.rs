@@ -1,12 +1,19 @@
 use std::{any::TypeId, collections::HashSet};
 
-use rust_ecs::{events::EventListener, systems::System, ComponentSignature, Entity, EntityManager};
+use rust_ecs::{
+    events::{Event, EventBus, EventListener},
+    systems::System,
+    ComponentSignature, Entity, EntityManager,
+};
 
 use crate::{
     components::{HealthComponent, ProjectileComponent},
     events::CollisionEvent,
+    relations::OwnedBy,
 };
 
+/// Applies the damage of the projectiles to the entities they collide with. The entities of the
+/// system are the projectiles.
 pub struct DamageSystem {
     signature: ComponentSignature,
     entities: HashSet<Entity>,
@@ -16,7 +23,10 @@ pub struct DamageSystem {
 impl Default for DamageSystem {
     fn default() -> Self {
         let event_types = [std::any::TypeId::of::<CollisionEvent>()];
-        Self { signature: ComponentSignature::default(), entities: Default::default(), event_types }
+        let mut signature = ComponentSignature::default();
+        signature.require_component::<ProjectileComponent>();
+        signature.require_group("projectile");
+        Self { signature, entities: Default::default(), event_types }
     }
 }
 
@@ -54,7 +64,8 @@ impl DamageSystem {
         let mut health_component = health_component.borrow_mut();
         let projectile_component = projectile_component.borrow();
 
-        if projectile_component.friendly {
+        // Players can't hit themselves.
+        if em.has_relation::<OwnedBy>(projectile, player) {
             return;
         }
 
@@ -62,12 +73,10 @@ impl DamageSystem {
 
         let played_is_dead = health_component.health <= 0;
 
-        drop(health_component);
-        drop(projectile_component);
-
-        em.destroy_entity(projectile);
+        let commands = em.commands();
+        commands.destroy_entity(projectile);
         if played_is_dead {
-            em.destroy_entity(player);
+            commands.destroy_recursive(player);
         }
     }
 
@@ -86,50 +95,52 @@ impl DamageSystem {
         let mut health_component = health_component.borrow_mut();
         let projectile_component = projectile_component.borrow();
 
-        if !projectile_component.friendly {
+        // Enemies are only hurt by the projectiles fired by the player.
+        let fired_by_player = em
+            .target::<OwnedBy>(projectile)
+            .is_some_and(|owner| em.tag_manager().has_tag(owner, "player"));
+        if !fired_by_player {
             return;
         }
         health_component.health -= projectile_component.damage;
 
         let is_dead = health_component.health <= 0;
 
-        drop(health_component);
-        drop(projectile_component);
-
-        em.destroy_entity(projectile);
+        let commands = em.commands();
+        commands.destroy_entity(projectile);
         if is_dead {
-            em.destroy_entity(enemy);
+            commands.destroy_recursive(enemy);
         }
     }
 }
 impl EventListener for DamageSystem {
-    fn on_event(&self, em: EntityManager, event: &rust_ecs::events::Event) {
+    fn on_event(&self, em: EntityManager, _event_bus: &EventBus, event: &Event) {
         let event = event.get_data::<CollisionEvent>().unwrap();
         let entity_a = event.entity_a;
         let entity_b = event.entity_b;
 
-        if em.tag_manager().has_tag(entity_a, "player")
-            && em.group_manager().entity_in_group(&entity_b, "projectile")
-        {
-            DamageSystem::on_player_projectile_collision(em.clone(), entity_a, entity_b);
+        // Events may outlive the entities they refer to.
+        if !em.is_alive(entity_a) || !em.is_alive(entity_b) {
+            return;
         }
 
-        if em.tag_manager().has_tag(entity_b, "player")
-            && em.group_manager().entity_in_group(&entity_a, "projectile")
-        {
-            DamageSystem::on_player_projectile_collision(em.clone(), entity_b, entity_a);
-        }
+        let (projectile, target) = if self.entities.contains(&entity_a) {
+            (entity_a, entity_b)
+        } else if self.entities.contains(&entity_b) {
+            (entity_b, entity_a)
+        } else {
+            return;
+        };
 
-        if em.group_manager().entity_in_group(&entity_a, "enemy")
-            && em.group_manager().entity_in_group(&entity_b, "projectile")
-        {
-            DamageSystem::on_enemy_projectile_collision(em.clone(), entity_a, entity_b);
+        // Only the player and the enemies have health.
+        if em.get_component::<HealthComponent>(&target).is_none() {
+            return;
         }
 
-        if em.group_manager().entity_in_group(&entity_b, "enemy")
-            && em.group_manager().entity_in_group(&entity_a, "projectile")
-        {
-            DamageSystem::on_enemy_projectile_collision(em.clone(), entity_b, entity_a);
+        if em.tag_manager().has_tag(target, "player") {
+            DamageSystem::on_player_projectile_collision(em, target, projectile);
+        } else {
+            DamageSystem::on_enemy_projectile_collision(em, target, projectile);
         }
     }
 }
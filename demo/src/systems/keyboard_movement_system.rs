@@ -1,5 +1,5 @@
 use macroquad::prelude::{KeyCode, Vec2};
-use rust_ecs::events::EventListener;
+use rust_ecs::events::{Event, EventBus, EventListener};
 use rust_ecs::systems::System;
 use rust_ecs::{ComponentSignature, Entity, EntityManager};
 use std::any::TypeId;
@@ -47,7 +47,7 @@ impl System for KeyboardMovementSystem {
 }
 
 impl EventListener for KeyboardMovementSystem {
-    fn on_event(&self, em: EntityManager, event: &rust_ecs::events::Event) {
+    fn on_event(&self, em: EntityManager, _event_bus: &EventBus, event: &Event) {
         for entity in &self.entities {
             let velocity = em.get_component::<VelocityComponent>(entity).unwrap();
             let sprite = em.get_component::<SpriteComponent>(entity).unwrap();
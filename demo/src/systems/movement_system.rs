@@ -3,12 +3,12 @@ use std::{cell::RefCell, collections::HashSet, rc::Rc};
 use rust_ecs::{
     events::{EventBus, EventListener},
     systems::System,
-    ComponentSignature, Entity, EntityManager,
+    ComponentSignature, Entity, EntityManager, Transform,
 };
 
-use crate::components::{TransformComponent, VelocityComponent};
+use crate::components::VelocityComponent;
 
-// The movement system uses a mutable TransformComponent and an immutable VelocityComponent,
+// The movement system uses a mutable Transform and an immutable VelocityComponent,
 // updating the entity position.
 pub struct MovementSystem {
     signature: ComponentSignature,
@@ -18,7 +18,7 @@ pub struct MovementSystem {
 impl Default for MovementSystem {
     fn default() -> Self {
         let mut signature = ComponentSignature::default();
-        signature.require_component::<TransformComponent>();
+        signature.require_component::<Transform>();
         signature.require_component::<VelocityComponent>();
         Self { signature, entities: Default::default() }
     }
@@ -47,13 +47,10 @@ impl System for MovementSystem {
         _event_bus: Rc<RefCell<EventBus>>,
         _resources: std::rc::Rc<std::cell::RefCell<rust_ecs::Resources>>,
     ) {
-        for entity in &self.entities {
-            let transform = entity_manager.get_component::<TransformComponent>(entity).unwrap();
-            let velocity = entity_manager.get_component::<VelocityComponent>(entity).unwrap();
-
-            let mut transform = transform.borrow_mut();
-            let velocity = velocity.borrow();
-            transform.0 += velocity.0 * delta_time.as_secs_f32();
-        }
+        entity_manager
+            .query::<(&mut Transform, &VelocityComponent)>()
+            .for_each(|_, (mut transform, velocity)| {
+                transform.translation += velocity.0 * delta_time.as_secs_f32();
+            });
     }
 }
@@ -44,22 +44,19 @@ impl System for ProjectileLifecycleSystem {
         _event_bus: Rc<RefCell<EventBus>>,
         _resources: Rc<RefCell<Resources>>,
     ) {
-        for entity in &self.entities {
-            let expired = {
-                let projectile_component =
-                    entity_manager.get_component::<ProjectileComponent>(entity).unwrap();
-                let projectile_component = projectile_component.borrow();
-                if projectile_component.created.elapsed().unwrap()
-                    >= projectile_component.max_duration
-                {
-                    true
-                } else {
-                    false
+        // Entities can't be destroyed while the query borrows the components, so collect the
+        // expired projectiles first.
+        let mut expired = Vec::new();
+        entity_manager
+            .query::<&ProjectileComponent>()
+            .for_each(|entity, projectile| {
+                if projectile.created.elapsed().unwrap() >= projectile.max_duration {
+                    expired.push(entity);
                 }
-            };
-            if expired {
-                entity_manager.destroy_entity(*entity);
-            }
+            });
+
+        for entity in expired {
+            entity_manager.destroy_entity(entity);
         }
     }
 }
@@ -1,4 +1,8 @@
-use std::{cell::RefCell, collections::HashSet, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
 use macroquad::{
     prelude::*,
@@ -7,25 +11,74 @@ use macroquad::{
 use rust_ecs::{
     events::{EventBus, EventListener},
     systems::System,
-    ComponentSignature, Entity, EntityManager,
+    Changed, ComponentSignature, Entity, EntityManager, GlobalTransform,
 };
 
-use crate::{
-    components::{SpriteComponent, TransformComponent},
-    resources::Camera,
-};
+use crate::{components::SpriteComponent, resources::Camera};
 
 pub struct RenderSystem {
     signature: ComponentSignature,
     entities: HashSet<Entity>,
+    // The entities sorted by z-index, with their z-index. Only sorted again when entities are added
+    // or removed, or when a sprite's z-index changes.
+    draw_order: RefCell<Vec<(Entity, i32)>>,
+    draw_order_dirty: Cell<bool>,
 }
 
 impl Default for RenderSystem {
     fn default() -> Self {
         let mut signature = ComponentSignature::default();
-        signature.require_component::<TransformComponent>();
+        signature.require_component::<GlobalTransform>();
         signature.require_component::<SpriteComponent>();
-        Self { signature, entities: Default::default() }
+        Self {
+            signature,
+            entities: Default::default(),
+            draw_order: Default::default(),
+            draw_order_dirty: Cell::new(true),
+        }
+    }
+}
+
+impl RenderSystem {
+    fn update_draw_order(&self, entity_manager: &EntityManager) {
+        let mut draw_order = self.draw_order.borrow_mut();
+        if self.draw_order_dirty.replace(false) {
+            *draw_order = self
+                .entities
+                .iter()
+                .map(|entity| {
+                    let sprite = entity_manager
+                        .get_component::<SpriteComponent>(entity)
+                        .unwrap();
+                    let z_index = sprite.borrow().z_index;
+                    (*entity, z_index)
+                })
+                .collect();
+            draw_order.sort_by_key(|(_, z_index)| *z_index);
+            return;
+        }
+
+        // Animated sprites change every frame, so only sort again if a z-index actually changed.
+        let mut changed = HashMap::new();
+        entity_manager
+            .query_filtered::<&SpriteComponent, Changed<SpriteComponent>>()
+            .for_each(|entity, sprite| {
+                changed.insert(entity, sprite.z_index);
+            });
+        if changed.is_empty() {
+            return;
+        }
+
+        let mut needs_sort = false;
+        for (entity, z_index) in draw_order.iter_mut() {
+            if let Some(new_z_index) = changed.get(entity) {
+                needs_sort |= *z_index != *new_z_index;
+                *z_index = *new_z_index;
+            }
+        }
+        if needs_sort {
+            draw_order.sort_by_key(|(_, z_index)| *z_index);
+        }
     }
 }
 
@@ -36,10 +89,12 @@ impl System for RenderSystem {
 
     fn add_entity(&mut self, entity: Entity) {
         self.entities.insert(entity);
+        self.draw_order_dirty.set(true);
     }
 
     fn remove_entity(&mut self, entity: Entity) {
         self.entities.remove(&entity);
+        self.draw_order_dirty.set(true);
     }
 
     fn update(
@@ -53,29 +108,28 @@ impl System for RenderSystem {
         let res = resources.borrow();
         let camera = res.get::<Camera>().unwrap();
 
-        let mut entities = self
-            .entities
-            .iter()
-            .map(|entity| {
-                let transform = entity_manager.get_component::<TransformComponent>(entity).unwrap();
-                let sprite = entity_manager.get_component::<SpriteComponent>(entity).unwrap();
-                (transform, sprite)
-            })
-            .collect::<Vec<_>>();
-        entities.sort_by_key(|(_, sprite)| sprite.borrow().z_index);
+        self.update_draw_order(&entity_manager);
 
-        for (transform, sprite) in entities {
+        for (entity, _) in self.draw_order.borrow().iter() {
+            let transform = entity_manager
+                .get_component::<GlobalTransform>(entity)
+                .unwrap();
+            let sprite = entity_manager
+                .get_component::<SpriteComponent>(entity)
+                .unwrap();
             let transform = transform.borrow();
+            let position = transform.translation();
             let sprite = sprite.borrow();
             let texture = asset_manager.get_texture(&sprite.sprite_name).unwrap();
             draw_texture_ex(
                 texture,
-                transform.0.x - camera.0.x,
-                transform.0.y - camera.0.y,
+                position.x - camera.0.x,
+                position.y - camera.0.y,
                 WHITE,
                 DrawTextureParams {
                     dest_size: Some(sprite.dst_size),
                     source: sprite.src_rect,
+                    rotation: transform.rotation(),
                     ..Default::default()
                 },
             );
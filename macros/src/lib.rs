@@ -31,3 +31,53 @@ pub fn component_macro_derive(input: TokenStream) -> TokenStream {
     };
     gen.into()
 }
+
+#[proc_macro_derive(Bundle)]
+pub fn bundle_macro_derive(input: TokenStream) -> TokenStream {
+    let ast: DeriveInput = syn::parse(input).unwrap();
+    let name = &ast.ident;
+    let syn::Data::Struct(data) = &ast.data else {
+        return syn::Error::new_spanned(&ast.ident, "Bundle can only be derived for structs")
+            .to_compile_error()
+            .into();
+    };
+
+    // Every field is itself a bundle, either a component or a nested bundle.
+    let field_types = data.fields.iter().map(|field| &field.ty).collect::<Vec<_>>();
+    let field_names = data
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match &field.ident {
+            Some(ident) => quote! { #ident },
+            None => {
+                let index = syn::Index::from(i);
+                quote! { #index }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // Fields whose type depends on the generic parameters are only bundles for some of them, so
+    // every field type is required to be a bundle.
+    let mut generics = ast.generics.clone();
+    let where_clause = generics.make_where_clause();
+    for ty in &field_types {
+        where_clause
+            .predicates
+            .push(syn::parse_quote! { #ty: rust_ecs::Bundle });
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let gen = quote! {
+        impl #impl_generics rust_ecs::Bundle for #name #ty_generics #where_clause {
+            fn add_to_signature(signature: &mut rust_ecs::ComponentSignature) {
+                #(<#field_types as rust_ecs::Bundle>::add_to_signature(signature);)*
+            }
+
+            fn insert_into(self, archetype: &mut rust_ecs::Archetype, row: usize, tick: rust_ecs::Tick) {
+                #(rust_ecs::Bundle::insert_into(self.#field_names, archetype, row, tick);)*
+            }
+        }
+    };
+    gen.into()
+}
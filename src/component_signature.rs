@@ -1,29 +1,116 @@
 use fixedbitset::FixedBitSet;
 
-use crate::Component;
+use crate::{
+    entity_manager::{ComponentTypeId, GroupManager, TagManager},
+    Component, Entity,
+};
 
 const MAX_COMPONENTS: usize = 32;
 
+/// The set of components an entity has or, for systems and queries, the components an entity must
+/// have to match. Signatures used for matching can also exclude components, require at least
+/// one component out of a set, and require or exclude tags and groups.
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 pub struct ComponentSignature {
     signature: FixedBitSet,
+    excluded: FixedBitSet,
+    any_of: Vec<FixedBitSet>,
+    tags: Vec<String>,
+    excluded_tags: Vec<String>,
+    groups: Vec<String>,
+    excluded_groups: Vec<String>,
 }
 
 impl Default for ComponentSignature {
     fn default() -> Self {
-        Self { signature: FixedBitSet::with_capacity(MAX_COMPONENTS) }
+        Self {
+            signature: FixedBitSet::with_capacity(MAX_COMPONENTS),
+            excluded: FixedBitSet::with_capacity(MAX_COMPONENTS),
+            any_of: Vec::new(),
+            tags: Vec::new(),
+            excluded_tags: Vec::new(),
+            groups: Vec::new(),
+            excluded_groups: Vec::new(),
+        }
     }
 }
 
 impl ComponentSignature {
     pub fn require_component<C: Component>(&mut self) {
-        let type_id = C::get_type_id();
-        self.signature.set(type_id, true);
+        self.require_component_type(C::get_type_id());
     }
 
     pub fn remove_component<C: Component>(&mut self) {
-        let type_id = C::get_type_id();
-        self.signature.set(type_id, false);
+        self.remove_component_type(C::get_type_id());
+    }
+
+    pub fn has_component<C: Component>(&self) -> bool {
+        self.has_component_type(C::get_type_id())
+    }
+
+    /// Entities with the component `C` don't match this signature.
+    pub fn exclude_component<C: Component>(&mut self) {
+        self.excluded.set(C::get_type_id(), true);
+    }
+
+    /// Entities must have at least one of the components required by `group` to match this
+    /// signature. Each call adds a new group.
+    pub fn require_any_of(&mut self, group: &ComponentSignature) {
+        self.any_of.push(group.signature.clone());
+    }
+
+    /// Entities must have `tag`, as their unique tag or as a label, to match this signature.
+    pub fn require_tag(&mut self, tag: &str) {
+        self.tags.push(tag.to_string());
+    }
+
+    /// Entities with `tag`, as their unique tag or as a label, don't match this signature.
+    pub fn exclude_tag(&mut self, tag: &str) {
+        self.excluded_tags.push(tag.to_string());
+    }
+
+    /// Entities must be in `group` to match this signature.
+    pub fn require_group(&mut self, group: &str) {
+        self.groups.push(group.to_string());
+    }
+
+    /// Entities in `group` don't match this signature.
+    pub fn exclude_group(&mut self, group: &str) {
+        self.excluded_groups.push(group.to_string());
+    }
+
+    /// Returns `true` if an entity with the components in `entity_signature` matches this
+    /// signature. The tags and groups are checked separately by `matches_tags_and_groups`.
+    pub fn matches(&self, entity_signature: &ComponentSignature) -> bool {
+        self.signature.is_subset(&entity_signature.signature)
+            && self.excluded.is_disjoint(&entity_signature.signature)
+            && self
+                .any_of
+                .iter()
+                .all(|group| !group.is_disjoint(&entity_signature.signature))
+    }
+
+    /// Returns `true` if the entity has the tags and is in the groups required by this signature,
+    /// and has none of the excluded ones.
+    pub fn matches_tags_and_groups(
+        &self,
+        entity: Entity,
+        tag_manager: &TagManager,
+        group_manager: &GroupManager,
+    ) -> bool {
+        self.tags.iter().all(|tag| tag_manager.has_tag(entity, tag))
+            && !self
+                .excluded_tags
+                .iter()
+                .any(|tag| tag_manager.has_tag(entity, tag))
+            && self
+                .groups
+                .iter()
+                .all(|group| group_manager.entity_in_group(&entity, group))
+            && !self
+                .excluded_groups
+                .iter()
+                .any(|group| group_manager.entity_in_group(&entity, group))
     }
 
     pub fn is_subset(&self, other: &ComponentSignature) -> bool {
@@ -33,4 +120,16 @@ impl ComponentSignature {
     pub fn is_superset(&self, other: &ComponentSignature) -> bool {
         self.signature.is_superset(&other.signature)
     }
+
+    pub(crate) fn require_component_type(&mut self, type_id: ComponentTypeId) {
+        self.signature.set(type_id, true);
+    }
+
+    pub(crate) fn remove_component_type(&mut self, type_id: ComponentTypeId) {
+        self.signature.set(type_id, false);
+    }
+
+    pub(crate) fn has_component_type(&self, type_id: ComponentTypeId) -> bool {
+        self.signature.contains(type_id)
+    }
 }
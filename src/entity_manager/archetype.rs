@@ -0,0 +1,446 @@
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
+
+use crate::ComponentSignature;
+
+use super::{
+    removed::RemovedComponentsStorage, ComponentTicks, ComponentTypeId, Entity, EntityId, Tick,
+};
+
+pub(crate) type ArchetypeId = usize;
+
+/// The archetype holding entities without any components. It is always the first archetype.
+pub(crate) const EMPTY_ARCHETYPE: ArchetypeId = 0;
+
+/// Where the components of an entity are stored: the archetype and the row inside its columns.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct EntityLocation {
+    pub archetype: ArchetypeId,
+    pub row: usize,
+}
+
+/// A component of an entity along with its change ticks. Each component has its own cell, shared
+/// between its column and the `ComponentRef` handles to it, so borrowing one component doesn't
+/// borrow the others, and handles keep working when their entity moves to another archetype.
+pub struct ComponentCell<C> {
+    pub(crate) value: RefCell<C>,
+    // The ticks are outside the value cell so they can be read and updated while the value is
+    // borrowed, as when a query both mutates a component and filters on it being changed.
+    pub(crate) ticks: Cell<ComponentTicks>,
+}
+
+impl<C> ComponentCell<C> {
+    fn new(value: C, ticks: ComponentTicks) -> Rc<Self> {
+        Rc::new(Self { value: RefCell::new(value), ticks: Cell::new(ticks) })
+    }
+}
+
+/// A column of the components of type `C` of an archetype, one cell per row.
+pub(crate) struct TypedColumn<C> {
+    pub cells: Vec<Rc<ComponentCell<C>>>,
+}
+
+/// Type erased operations over a `TypedColumn`, used when moving entities between archetypes.
+pub(crate) trait AnyColumn {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    /// Creates an empty column for the same component type.
+    fn new_empty(&self) -> Box<dyn AnyColumn>;
+    /// Removes the component at `row`, moving the last component into its place, and records it
+    /// in `removed` as removed from `entity` at `tick`.
+    fn swap_remove_into_removed(
+        &mut self,
+        row: usize,
+        type_id: ComponentTypeId,
+        entity: Entity,
+        tick: Tick,
+        removed: &mut RemovedComponentsStorage,
+    );
+    /// Removes the component at `row`, moving the last component into its place, and pushes it
+    /// into `dst`, which must be a column for the same component type.
+    fn swap_remove_into(&mut self, row: usize, dst: &mut dyn AnyColumn);
+}
+
+impl<C: 'static> TypedColumn<C> {
+    pub fn new() -> Self {
+        Self { cells: Vec::new() }
+    }
+
+    /// Adds a component, stamped as added at `tick`, to the end of the column.
+    pub fn push(&mut self, value: C, tick: Tick) {
+        self.cells
+            .push(ComponentCell::new(value, ComponentTicks::new(tick)));
+    }
+
+    /// Replaces the component at `row`, stamping it as changed at `tick`. The new value gets a new
+    /// cell, so handles to the old value don't need to be released first.
+    pub fn replace(&mut self, row: usize, value: C, tick: Tick) {
+        let ticks = ComponentTicks { changed: tick, ..self.cells[row].ticks.get() };
+        self.cells[row] = ComponentCell::new(value, ticks);
+    }
+}
+
+impl<C: 'static> AnyColumn for TypedColumn<C> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn new_empty(&self) -> Box<dyn AnyColumn> {
+        Box::new(TypedColumn::<C>::new())
+    }
+
+    fn swap_remove_into_removed(
+        &mut self,
+        row: usize,
+        type_id: ComponentTypeId,
+        entity: Entity,
+        tick: Tick,
+        removed: &mut RemovedComponentsStorage,
+    ) {
+        let cell = self.cells.swap_remove(row);
+        removed.get_or_insert::<C>(type_id).push(entity, cell, tick);
+    }
+
+    fn swap_remove_into(&mut self, row: usize, dst: &mut dyn AnyColumn) {
+        let cell = self.cells.swap_remove(row);
+        let dst = dst.as_any_mut().downcast_mut::<TypedColumn<C>>().unwrap();
+        dst.cells.push(cell);
+    }
+}
+
+/// A table of all the entities sharing the same `ComponentSignature`. Each component type in the
+/// signature has its own column, and the components of an entity share the same row in every
+/// column.
+pub struct Archetype {
+    signature: ComponentSignature,
+    entities: Rc<RefCell<Vec<Entity>>>,
+    // Columns are created when the first entity moves into the archetype.
+    columns: HashMap<ComponentTypeId, Box<dyn AnyColumn>>,
+}
+
+impl Archetype {
+    fn new(signature: ComponentSignature) -> Self {
+        Self { signature, entities: Default::default(), columns: HashMap::new() }
+    }
+
+    pub(crate) fn signature(&self) -> &ComponentSignature {
+        &self.signature
+    }
+
+    pub(crate) fn entities(&self) -> &Rc<RefCell<Vec<Entity>>> {
+        &self.entities
+    }
+
+    pub(crate) fn column<C: 'static>(&self, type_id: ComponentTypeId) -> Option<&TypedColumn<C>> {
+        self.columns
+            .get(&type_id)
+            .map(|c| c.as_any().downcast_ref::<TypedColumn<C>>().unwrap())
+    }
+
+    pub(crate) fn column_or_insert<C: 'static>(
+        &mut self,
+        type_id: ComponentTypeId,
+    ) -> &mut TypedColumn<C> {
+        self.columns
+            .entry(type_id)
+            .or_insert_with(|| Box::new(TypedColumn::<C>::new()))
+            .as_any_mut()
+            .downcast_mut::<TypedColumn<C>>()
+            .unwrap()
+    }
+
+    /// Writes the component of the entity at `row`, replacing it if the entity already has one,
+    /// and pushing it otherwise. Components of an entity that was just moved into the archetype
+    /// are pushed into their column, so the entity must be in the last row.
+    pub(crate) fn insert_component<C: 'static>(
+        &mut self,
+        type_id: ComponentTypeId,
+        row: usize,
+        value: C,
+        tick: Tick,
+    ) {
+        let column = self.column_or_insert::<C>(type_id);
+        if row < column.cells.len() {
+            column.replace(row, value, tick);
+        } else {
+            column.push(value, tick);
+        }
+    }
+}
+
+/// The archetype storage for all the components in an `EntityManager`.
+pub(crate) struct Archetypes {
+    archetypes: Vec<Archetype>,
+    by_signature: HashMap<ComponentSignature, ArchetypeId>,
+    // The location of each entity, indexed by entity id.
+    locations: Vec<Option<EntityLocation>>,
+}
+
+impl Default for Archetypes {
+    fn default() -> Self {
+        let signature = ComponentSignature::default();
+        let mut by_signature = HashMap::new();
+        by_signature.insert(signature.clone(), EMPTY_ARCHETYPE);
+        Self {
+            archetypes: vec![Archetype::new(signature)],
+            by_signature,
+            locations: Default::default(),
+        }
+    }
+}
+
+impl Archetypes {
+    pub fn iter(&self) -> impl Iterator<Item = &Archetype> {
+        self.archetypes.iter()
+    }
+
+    pub fn get(&self, id: ArchetypeId) -> &Archetype {
+        &self.archetypes[id]
+    }
+
+    pub fn get_mut(&mut self, id: ArchetypeId) -> &mut Archetype {
+        &mut self.archetypes[id]
+    }
+
+    pub fn location(&self, entity_id: EntityId) -> Option<EntityLocation> {
+        self.locations.get(entity_id).copied().flatten()
+    }
+
+    /// Returns the archetype for `signature`, creating it if needed.
+    pub fn get_or_insert(&mut self, signature: &ComponentSignature) -> ArchetypeId {
+        if let Some(id) = self.by_signature.get(signature) {
+            return *id;
+        }
+
+        let id = self.archetypes.len();
+        self.archetypes.push(Archetype::new(signature.clone()));
+        self.by_signature.insert(signature.clone(), id);
+        id
+    }
+
+    /// Adds a new entity, without components, to the empty archetype.
+    pub fn insert_entity(&mut self, entity: Entity) {
+        let mut entities = self.archetypes[EMPTY_ARCHETYPE].entities.borrow_mut();
+        let row = entities.len();
+        entities.push(entity);
+
+        if self.locations.len() <= entity.id() {
+            self.locations.resize(entity.id() + 1, None);
+        }
+        self.locations[entity.id()] = Some(EntityLocation { archetype: EMPTY_ARCHETYPE, row });
+    }
+
+    /// Removes the entity, recording all of its components in `removed`.
+    pub fn remove_entity(
+        &mut self,
+        entity: Entity,
+        tick: Tick,
+        removed: &mut RemovedComponentsStorage,
+    ) {
+        let Some(location) = self.location(entity.id()) else {
+            return;
+        };
+        self.locations[entity.id()] = None;
+
+        let archetype = &mut self.archetypes[location.archetype];
+        for (type_id, column) in archetype.columns.iter_mut() {
+            column.swap_remove_into_removed(location.row, *type_id, entity, tick, removed);
+        }
+        archetype.entities.borrow_mut().swap_remove(location.row);
+        self.fix_moved_entity(location);
+    }
+
+    /// Moves the entity to the archetype `dst`. Components that are part of the `dst` signature
+    /// are moved along with the entity, the others are recorded in `removed`. Returns the new
+    /// location.
+    pub fn move_entity(
+        &mut self,
+        entity: Entity,
+        dst: ArchetypeId,
+        tick: Tick,
+        removed: &mut RemovedComponentsStorage,
+    ) -> EntityLocation {
+        let src_location = self.location(entity.id()).unwrap();
+        if src_location.archetype == dst {
+            return src_location;
+        }
+
+        let (src_archetype, dst_archetype) = self.pair_mut(src_location.archetype, dst);
+        for (type_id, column) in src_archetype.columns.iter_mut() {
+            if dst_archetype.signature.has_component_type(*type_id) {
+                let dst_column = dst_archetype
+                    .columns
+                    .entry(*type_id)
+                    .or_insert_with(|| column.new_empty());
+                column.swap_remove_into(src_location.row, dst_column.as_mut());
+            } else {
+                column.swap_remove_into_removed(src_location.row, *type_id, entity, tick, removed);
+            }
+        }
+        src_archetype
+            .entities
+            .borrow_mut()
+            .swap_remove(src_location.row);
+
+        let mut dst_entities = dst_archetype.entities.borrow_mut();
+        let dst_location = EntityLocation { archetype: dst, row: dst_entities.len() };
+        dst_entities.push(entity);
+        drop(dst_entities);
+
+        self.locations[entity.id()] = Some(dst_location);
+        self.fix_moved_entity(src_location);
+        dst_location
+    }
+
+    // After a swap remove, the last entity of the archetype is moved into the removed row.
+    fn fix_moved_entity(&mut self, removed: EntityLocation) {
+        let entities = self.archetypes[removed.archetype].entities.borrow();
+        if let Some(moved) = entities.get(removed.row) {
+            self.locations[moved.id()] = Some(removed);
+        }
+    }
+
+    fn pair_mut(&mut self, a: ArchetypeId, b: ArchetypeId) -> (&mut Archetype, &mut Archetype) {
+        if a < b {
+            let (left, right) = self.archetypes.split_at_mut(b);
+            (&mut left[a], &mut right[0])
+        } else {
+            let (left, right) = self.archetypes.split_at_mut(a);
+            (&mut right[0], &mut left[b])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity_manager::{impl_component, Component};
+
+    #[derive(Debug, PartialEq)]
+    struct Position(u32);
+    #[derive(Debug, PartialEq)]
+    struct Velocity(u32);
+    impl_component!(Position, Velocity);
+
+    // Spawns `count` entities with a `Position` equal to their id, all in the same archetype.
+    fn spawn_positions(archetypes: &mut Archetypes, count: usize) -> (ArchetypeId, Vec<Entity>) {
+        let mut signature = ComponentSignature::default();
+        signature.require_component::<Position>();
+        let archetype = archetypes.get_or_insert(&signature);
+        let mut removed = RemovedComponentsStorage::default();
+
+        let entities: Vec<Entity> = (0..count).map(|id| Entity::new(id, 0)).collect();
+        for entity in &entities {
+            archetypes.insert_entity(*entity);
+            let location = archetypes.move_entity(*entity, archetype, 0, &mut removed);
+            archetypes.get_mut(archetype).insert_component(
+                Position::get_type_id(),
+                location.row,
+                Position(entity.id() as u32),
+                0,
+            );
+        }
+        (archetype, entities)
+    }
+
+    fn position(archetypes: &Archetypes, entity: Entity) -> u32 {
+        let location = archetypes.location(entity.id()).unwrap();
+        let column = archetypes
+            .get(location.archetype)
+            .column::<Position>(Position::get_type_id())
+            .unwrap();
+        let value = column.cells[location.row].value.borrow();
+        value.0
+    }
+
+    fn entities_of(archetypes: &Archetypes, archetype: ArchetypeId) -> Vec<Entity> {
+        archetypes.get(archetype).entities().borrow().clone()
+    }
+
+    #[test]
+    fn removing_a_middle_row_relocates_the_last_entity() {
+        let mut archetypes = Archetypes::default();
+        let (archetype, entities) = spawn_positions(&mut archetypes, 3);
+        let mut removed = RemovedComponentsStorage::default();
+
+        archetypes.remove_entity(entities[1], 1, &mut removed);
+
+        assert!(archetypes.location(entities[1].id()).is_none());
+        assert_eq!(
+            entities_of(&archetypes, archetype),
+            vec![entities[0], entities[2]]
+        );
+        assert_eq!(archetypes.location(entities[2].id()).unwrap().row, 1);
+        assert_eq!(position(&archetypes, entities[0]), 0);
+        assert_eq!(position(&archetypes, entities[2]), 2);
+        assert!(removed.get::<Position>(Position::get_type_id()).is_some());
+    }
+
+    #[test]
+    fn removing_the_last_row_keeps_the_other_locations() {
+        let mut archetypes = Archetypes::default();
+        let (archetype, entities) = spawn_positions(&mut archetypes, 3);
+        let mut removed = RemovedComponentsStorage::default();
+
+        archetypes.remove_entity(entities[2], 1, &mut removed);
+
+        assert!(archetypes.location(entities[2].id()).is_none());
+        assert_eq!(
+            entities_of(&archetypes, archetype),
+            vec![entities[0], entities[1]]
+        );
+        assert_eq!(archetypes.location(entities[0].id()).unwrap().row, 0);
+        assert_eq!(archetypes.location(entities[1].id()).unwrap().row, 1);
+        assert_eq!(position(&archetypes, entities[1]), 1);
+    }
+
+    #[test]
+    fn moving_a_middle_row_carries_its_components_and_relocates_the_last_entity() {
+        let mut archetypes = Archetypes::default();
+        let (src, entities) = spawn_positions(&mut archetypes, 3);
+        let mut signature = archetypes.get(src).signature().clone();
+        signature.require_component::<Velocity>();
+        let dst = archetypes.get_or_insert(&signature);
+        let mut removed = RemovedComponentsStorage::default();
+
+        let location = archetypes.move_entity(entities[1], dst, 1, &mut removed);
+
+        assert_eq!(location.archetype, dst);
+        assert_eq!(location.row, 0);
+        assert_eq!(
+            entities_of(&archetypes, src),
+            vec![entities[0], entities[2]]
+        );
+        assert_eq!(entities_of(&archetypes, dst), vec![entities[1]]);
+        assert_eq!(archetypes.location(entities[2].id()).unwrap().row, 1);
+        assert_eq!(position(&archetypes, entities[1]), 1);
+        assert_eq!(position(&archetypes, entities[2]), 2);
+        assert!(removed.get::<Position>(Position::get_type_id()).is_none());
+    }
+
+    #[test]
+    fn moving_the_last_row_drops_the_components_missing_from_the_destination() {
+        let mut archetypes = Archetypes::default();
+        let (src, entities) = spawn_positions(&mut archetypes, 3);
+        let mut removed = RemovedComponentsStorage::default();
+
+        let location = archetypes.move_entity(entities[2], EMPTY_ARCHETYPE, 1, &mut removed);
+
+        assert_eq!(location.archetype, EMPTY_ARCHETYPE);
+        assert_eq!(
+            entities_of(&archetypes, src),
+            vec![entities[0], entities[1]]
+        );
+        assert_eq!(archetypes.location(entities[1].id()).unwrap().row, 1);
+        assert_eq!(position(&archetypes, entities[1]), 1);
+        assert!(removed.get::<Position>(Position::get_type_id()).is_some());
+    }
+}
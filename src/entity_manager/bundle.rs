@@ -0,0 +1,60 @@
+use crate::component_signature::ComponentSignature;
+
+use super::{archetype::Archetype, Component, Tick};
+
+/// A group of components added to an entity in a single step, moving the entity to its new
+/// archetype only once. Implemented for every component, for tuples of up to eight bundles, and
+/// for structs deriving `Bundle`, whose fields must all be bundles:
+///
+/// ```ignore
+/// #[derive(Bundle)]
+/// struct ProjectileBundle {
+///     transform: Transform,
+///     velocity: VelocityComponent,
+///     sprite: SpriteComponent,
+/// }
+/// ```
+pub trait Bundle: 'static {
+    /// Adds the components of the bundle to `signature`.
+    fn add_to_signature(signature: &mut ComponentSignature);
+
+    /// Writes the components of the bundle to the entity at `row`, which must already be in an
+    /// archetype including them.
+    #[doc(hidden)]
+    fn insert_into(self, archetype: &mut Archetype, row: usize, tick: Tick);
+}
+
+impl<C: Component + 'static> Bundle for C {
+    fn add_to_signature(signature: &mut ComponentSignature) {
+        signature.require_component::<C>();
+    }
+
+    fn insert_into(self, archetype: &mut Archetype, row: usize, tick: Tick) {
+        archetype.insert_component(C::get_type_id(), row, self, tick);
+    }
+}
+
+macro_rules! impl_bundle_tuple {
+    ($($param:ident),*) => {
+        impl<$($param: Bundle),*> Bundle for ($($param,)*) {
+            fn add_to_signature(signature: &mut ComponentSignature) {
+                $($param::add_to_signature(signature);)*
+            }
+
+            #[allow(non_snake_case)]
+            fn insert_into(self, archetype: &mut Archetype, row: usize, tick: Tick) {
+                let ($($param,)*) = self;
+                $($param.insert_into(archetype, row, tick);)*
+            }
+        }
+    };
+}
+
+impl_bundle_tuple!(A);
+impl_bundle_tuple!(A, B);
+impl_bundle_tuple!(A, B, C);
+impl_bundle_tuple!(A, B, C, D);
+impl_bundle_tuple!(A, B, C, D, E);
+impl_bundle_tuple!(A, B, C, D, E, F);
+impl_bundle_tuple!(A, B, C, D, E, F, G);
+impl_bundle_tuple!(A, B, C, D, E, F, G, H);
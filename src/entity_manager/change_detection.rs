@@ -0,0 +1,178 @@
+use std::{
+    cell::Cell,
+    ops::{Deref, DerefMut},
+};
+
+/// A point in time of an `EntityManager`. The tick advances as every system starts running, so
+/// components mutated by a system are stamped with a tick newer than the last run of every system,
+/// including its own: each change is reported once to every system, in its next run.
+pub type Tick = u64;
+
+/// When a component was added to its entity, and when it was last mutably accessed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ComponentTicks {
+    pub added: Tick,
+    pub changed: Tick,
+}
+
+impl ComponentTicks {
+    pub(crate) fn new(tick: Tick) -> Self {
+        Self { added: tick, changed: tick }
+    }
+
+    /// Returns `true` if the component was added after `last_run`.
+    pub fn is_added(&self, last_run: Tick) -> bool {
+        self.added > last_run
+    }
+
+    /// Returns `true` if the component was added or mutated after `last_run`.
+    pub fn is_changed(&self, last_run: Tick) -> bool {
+        self.changed > last_run
+    }
+}
+
+/// A mutable reference to a component, yielded by queries for `&mut C`. The component is only
+/// marked as changed when it is actually mutated through the reference.
+pub struct Mut<'a, C> {
+    value: &'a mut C,
+    ticks: &'a Cell<ComponentTicks>,
+    change_tick: Tick,
+}
+
+impl<'a, C> Mut<'a, C> {
+    pub(crate) fn new(
+        value: &'a mut C,
+        ticks: &'a Cell<ComponentTicks>,
+        change_tick: Tick,
+    ) -> Self {
+        Self { value, ticks, change_tick }
+    }
+
+    pub fn ticks(&self) -> ComponentTicks {
+        self.ticks.get()
+    }
+}
+
+impl<C> Deref for Mut<'_, C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        self.value
+    }
+}
+
+impl<C> DerefMut for Mut<'_, C> {
+    fn deref_mut(&mut self) -> &mut C {
+        let mut ticks = self.ticks.get();
+        ticks.changed = self.change_tick;
+        self.ticks.set(ticks);
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, time::Duration};
+
+    use super::*;
+    use crate::{
+        entity_manager::impl_component, events::EventListener, systems::System, Added,
+        AssetManager, Changed, ComponentSignature, Entity, EntityComponentSystem, EntityManager,
+        EventBus, Resources,
+    };
+
+    struct Value(u32);
+    impl_component!(Value);
+
+    // The frame of a run, the system, and whether `Value` was added and changed since its last run.
+    type Run = (u32, &'static str, bool, bool);
+
+    // Records, on each run, whether `Value` was added and whether it changed since the previous
+    // run of the system, then mutates it if `mutate_on` is the current frame.
+    struct Recorder {
+        signature: ComponentSignature,
+        name: &'static str,
+        frame: Rc<Cell<u32>>,
+        mutate_on: Option<u32>,
+        log: Rc<RefCell<Vec<Run>>>,
+    }
+
+    impl EventListener for Recorder {}
+
+    impl System for Recorder {
+        fn signature(&self) -> &ComponentSignature {
+            &self.signature
+        }
+
+        fn add_entity(&mut self, _entity: Entity) {}
+
+        fn remove_entity(&mut self, _entity: Entity) {}
+
+        fn update(
+            &self,
+            _delta_time: Duration,
+            _asset_manager: &AssetManager,
+            em: EntityManager,
+            _event_bus: Rc<RefCell<EventBus>>,
+            _resources: Rc<RefCell<Resources>>,
+        ) {
+            let frame = self.frame.get();
+            let added = !em
+                .query_filtered::<&Value, Added<Value>>()
+                .entities()
+                .is_empty();
+            let changed = !em
+                .query_filtered::<&Value, Changed<Value>>()
+                .entities()
+                .is_empty();
+            self.log
+                .borrow_mut()
+                .push((frame, self.name, added, changed));
+            if self.mutate_on == Some(frame) {
+                em.query::<&mut Value>()
+                    .for_each(|_, mut value| value.0 += 1);
+            }
+        }
+    }
+
+    #[test]
+    fn changes_are_seen_once_by_every_system() {
+        let mut ecs = EntityComponentSystem::new();
+        let frame = Rc::new(Cell::new(0));
+        let log = Rc::new(RefCell::new(Vec::new()));
+        for (name, mutate_on) in [("a", Some(1)), ("b", None)] {
+            ecs.add_system(Recorder {
+                signature: ComponentSignature::default(),
+                name,
+                frame: frame.clone(),
+                mutate_on,
+                log: log.clone(),
+            });
+        }
+        let em = ecs.entity_manager();
+        let entity = em.create_entity();
+        em.add_component(entity, Value(0));
+
+        for current in 0..4 {
+            frame.set(current);
+            ecs.update(Duration::ZERO);
+        }
+
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                // Added components count as changed.
+                (0, "a", true, true),
+                (0, "b", true, true),
+                // "a" mutates the value after reading, so "b" sees the change in the same frame.
+                (1, "a", false, false),
+                (1, "b", false, true),
+                // And "a" sees its own change in its next run.
+                (2, "a", false, true),
+                (2, "b", false, false),
+                (3, "a", false, false),
+                (3, "b", false, false),
+            ]
+        );
+    }
+}
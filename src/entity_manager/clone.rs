@@ -0,0 +1,123 @@
+use crate::component_signature::ComponentSignature;
+
+use super::{
+    archetype::Archetype,
+    em::EntityManagerInner,
+    entity_builder::{EntityTags, Insert},
+    Bundle, Component, Entity,
+};
+
+/// The operations copying a component registered with `EntityManager::register_cloneable`.
+pub(crate) struct Cloneable {
+    add_to_signature: fn(&mut ComponentSignature),
+    // Copies the component of the entity at `row`, returning the write of the copy, or `None` if
+    // the archetype doesn't store the component.
+    clone: fn(&Archetype, usize) -> Option<Insert>,
+}
+
+impl Cloneable {
+    pub fn new<C: Component + Clone + 'static>() -> Self {
+        Self { add_to_signature: <C as Bundle>::add_to_signature, clone: clone_component::<C> }
+    }
+}
+
+fn clone_component<C: Component + Clone + 'static>(
+    archetype: &Archetype,
+    row: usize,
+) -> Option<Insert> {
+    let column = archetype.column::<C>(C::get_type_id())?;
+    let value = column.cells[row].value.borrow().clone();
+    Some(Box::new(move |archetype, row, tick| {
+        value.insert_into(archetype, row, tick)
+    }))
+}
+
+impl EntityManagerInner {
+    /// Registers the Component `C` as cloneable, so `clone_entity` copies it.
+    pub fn register_cloneable<C: Component + Clone + 'static>(&mut self) {
+        self.cloneable
+            .insert(C::get_type_id(), Cloneable::new::<C>());
+    }
+
+    /// Creates a copy of the entity with its cloneable components, labels and groups. If
+    /// `copy_tag` is `true`, the unique tag of the entity moves to the copy, and the entity loses
+    /// it. Returns `None` if the entity is not alive.
+    pub fn clone_entity(&mut self, entity: Entity, copy_tag: bool) -> Option<Entity> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+
+        let location = self.archetypes.location(entity.id())?;
+        let archetype = self.archetypes.get(location.archetype);
+        let mut signature = ComponentSignature::default();
+        let mut inserts = Vec::new();
+        for cloneable in self.cloneable.values() {
+            if let Some(insert) = (cloneable.clone)(archetype, location.row) {
+                (cloneable.add_to_signature)(&mut signature);
+                inserts.push(insert);
+            }
+        }
+
+        // As tags are unique, setting the tag on the copy takes it from the entity.
+        let tags = EntityTags {
+            tag: copy_tag.then(|| self.tag_manager.get_tag(entity)).flatten(),
+            labels: self.tag_manager.labels_of(entity),
+            groups: self.group_manager.groups_of(&entity),
+        };
+        Some(self.create_entity_with(&signature, inserts, &tags))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entity_manager::{impl_component, EntityManager};
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Health(u32);
+    struct Sprite;
+    impl_component!(Health, Sprite);
+
+    #[test]
+    fn only_cloneable_components_are_copied() {
+        let em = EntityManager::new();
+        em.register_cloneable::<Health>();
+        let entity = em.spawn().with((Health(3), Sprite)).id();
+
+        let copy = em.clone_entity(entity, false).unwrap();
+        assert_eq!(
+            *em.get_component::<Health>(&copy).unwrap().borrow(),
+            Health(3)
+        );
+        assert!(em.get_component::<Sprite>(&copy).is_none());
+        assert!(em.get_component::<Sprite>(&entity).is_some());
+    }
+
+    #[test]
+    fn copying_the_tag_moves_it_to_the_copy() {
+        let em = EntityManager::new();
+        let entity = em.spawn().tag("player").id();
+
+        let kept = em.clone_entity(entity, false).unwrap();
+        assert_eq!(em.tag_manager().get_tag(kept), None);
+        assert_eq!(em.tag_manager().get_entity("player"), Some(entity));
+
+        let copy = em.clone_entity(entity, true).unwrap();
+        assert_eq!(em.tag_manager().get_entity("player"), Some(copy));
+        assert_eq!(em.tag_manager().get_tag(entity), None);
+    }
+
+    #[test]
+    fn labels_and_groups_are_copied() {
+        let em = EntityManager::new();
+        let entity = em.spawn().label("hero").group("allies").id();
+
+        let copy = em.clone_entity(entity, false).unwrap();
+        assert_eq!(em.tag_manager().labels_of(copy), vec!["hero".to_string()]);
+        assert_eq!(
+            em.group_manager().groups_of(&copy),
+            vec!["allies".to_string()]
+        );
+        assert!(em.entities_with_tag("hero").contains(&copy));
+        assert!(em.entities_in_group("allies").contains(&copy));
+    }
+}
@@ -0,0 +1,211 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::{em::EntityManagerInner, entity::EntityAllocator, Bundle, Component, Entity, Relation};
+
+/// A structural change recorded by `Commands`.
+pub(crate) type Command = Box<dyn FnOnce(&mut EntityManagerInner)>;
+
+/// A buffer of structural changes, applied by `EntityComponentSystem::update` at its sync points:
+/// at the start of the update and after each system runs. Recording commands never borrows the
+/// `EntityManager` or any component, so systems and event listeners can record them while
+/// iterating queries or holding component borrows.
+///
+/// Commands are applied in the order they were recorded. Commands targeting entities that are no
+/// longer alive when the buffer is applied are ignored. Commands recorded while applying the
+/// buffer, such as by component hooks, are applied right after it, for up to
+/// `EntityComponentSystem::set_max_command_rounds` rounds.
+#[derive(Clone)]
+pub struct Commands {
+    queue: Rc<RefCell<Vec<Command>>>,
+    allocator: Rc<RefCell<EntityAllocator>>,
+}
+
+impl Commands {
+    pub(crate) fn new(allocator: Rc<RefCell<EntityAllocator>>) -> Self {
+        Self { queue: Default::default(), allocator }
+    }
+
+    /// Reserves a new entity, spawned when the buffer is applied. The returned entity is not alive
+    /// until then, but can be used in other commands.
+    pub fn spawn(&self) -> Entity {
+        let entity = self.allocator.borrow_mut().reserve();
+        self.push(move |em| em.spawn_reserved(entity));
+        entity
+    }
+
+    /// Reserves a new entity, spawned as an instance of the prefab named `name` when the buffer is
+    /// applied. Nothing is spawned if no prefab is registered with that name. Components added
+    /// to the entity by later commands override the ones of the prefab.
+    pub fn instantiate(&self, name: &str) -> Entity {
+        let entity = self.allocator.borrow_mut().reserve();
+        let name = name.to_string();
+        self.push(move |em| {
+            em.instantiate_reserved(entity, &name);
+        });
+        entity
+    }
+
+    /// Adds the Component `C` to the entity, replacing the existing component of the same type.
+    pub fn add_component<C: Component + 'static>(&self, entity: Entity, component: C) {
+        self.push(move |em| {
+            em.add_component(entity, component);
+        });
+    }
+
+    /// Adds the components of the bundle to the entity, replacing the existing components of the
+    /// same types.
+    pub fn add_bundle<B: Bundle>(&self, entity: Entity, bundle: B) {
+        self.push(move |em| {
+            em.add_bundle(entity, bundle);
+        });
+    }
+
+    /// Removes the Component `C` from the entity.
+    pub fn remove_component<C: Component + 'static>(&self, entity: Entity) {
+        self.push(move |em| {
+            em.remove_component::<C>(entity);
+        });
+    }
+
+    /// Despawns the entity. Unlike `EntityManager::destroy_entity`, the entity is despawned as soon
+    /// as the buffer is applied instead of waiting for the next update.
+    pub fn destroy_entity(&self, entity: Entity) {
+        self.push(move |em| {
+            em.despawn(entity);
+        });
+    }
+
+    /// Despawns the entity and all of its descendants as soon as the buffer is applied.
+    pub fn destroy_recursive(&self, entity: Entity) {
+        self.push(move |em| {
+            for descendant in em.descendants(entity) {
+                em.despawn(descendant);
+            }
+            em.despawn(entity);
+        });
+    }
+
+    /// Makes `parent` the parent of `child`.
+    pub fn set_parent(&self, child: Entity, parent: Entity) {
+        self.push(move |em| {
+            em.set_parent(child, parent);
+        });
+    }
+
+    /// Adds the relation `R` from `source` to `target`.
+    pub fn add_relation<R: Relation>(&self, source: Entity, target: Entity) {
+        self.push(move |em| {
+            em.add_relation::<R>(source, target);
+        });
+    }
+
+    /// Removes the relation `R` from `source` to `target`.
+    pub fn remove_relation<R: Relation>(&self, source: Entity, target: Entity) {
+        self.push(move |em| {
+            em.remove_relation::<R>(source, target);
+        });
+    }
+
+    /// Sets the unique tag of the entity.
+    pub fn set_tag(&self, entity: Entity, tag: &str) {
+        let tag = tag.to_string();
+        self.push(move |em| {
+            if em.is_alive(entity) {
+                em.tag_manager.set_tag(entity, &tag);
+            }
+        });
+    }
+
+    /// Removes the unique tag of the entity, keeping its labels.
+    pub fn remove_tag(&self, entity: Entity) {
+        self.push(move |em| {
+            if em.is_alive(entity) {
+                em.tag_manager.remove_tag(entity);
+            }
+        });
+    }
+
+    /// Adds `label` to the labels of the entity.
+    pub fn add_label(&self, entity: Entity, label: &str) {
+        let label = label.to_string();
+        self.push(move |em| {
+            if em.is_alive(entity) {
+                em.tag_manager.add_label(entity, &label);
+            }
+        });
+    }
+
+    /// Removes `label` from the labels of the entity.
+    pub fn remove_label(&self, entity: Entity, label: &str) {
+        let label = label.to_string();
+        self.push(move |em| {
+            if em.is_alive(entity) {
+                em.tag_manager.remove_label(entity, &label);
+            }
+        });
+    }
+
+    /// Adds the entity to `group`.
+    pub fn add_entity_to_group(&self, entity: Entity, group: &str) {
+        let group = group.to_string();
+        self.push(move |em| {
+            if em.is_alive(entity) {
+                em.group_manager.add_entity_to_group(&entity, &group);
+            }
+        });
+    }
+
+    /// Removes the entity from `group`.
+    pub fn remove_entity_from_group(&self, entity: Entity, group: &str) {
+        let group = group.to_string();
+        self.push(move |em| {
+            if em.is_alive(entity) {
+                em.group_manager.remove_entity_from_group(&entity, &group);
+            }
+        });
+    }
+
+    /// Returns `true` if no commands are waiting to be applied.
+    pub fn is_empty(&self) -> bool {
+        self.queue.borrow().is_empty()
+    }
+
+    /// Takes the recorded commands, leaving the buffer empty.
+    pub(crate) fn take(&self) -> Vec<Command> {
+        self.queue.take()
+    }
+
+    fn push(&self, apply: impl FnOnce(&mut EntityManagerInner) + 'static) {
+        self.queue.borrow_mut().push(Box::new(apply));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc, time::Duration};
+
+    use crate::{entity_manager::impl_component, EntityComponentSystem};
+
+    struct Counter;
+    impl_component!(Counter);
+
+    #[test]
+    fn commands_recording_themselves_forever_are_cut() {
+        let mut ecs = EntityComponentSystem::new();
+        ecs.set_max_command_rounds(3);
+        let em = ecs.entity_manager();
+        let inserts = Rc::new(Cell::new(0));
+        let hook_inserts = inserts.clone();
+        em.on_insert::<Counter>(move |entity, _, world| {
+            hook_inserts.set(hook_inserts.get() + 1);
+            world.commands().add_component(entity, Counter);
+        });
+
+        let entity = em.create_entity();
+        em.add_component(entity, Counter);
+        ecs.update(Duration::ZERO);
+
+        assert_eq!(inserts.get(), 4);
+        assert!(em.commands().is_empty());
+    }
+}
@@ -1,8 +1,12 @@
 use std::{
     any::Any,
+    cell::{BorrowError, BorrowMutError, Cell, Ref, RefMut},
+    rc::Rc,
     sync::atomic::{AtomicUsize, Ordering},
 };
 
+use super::{archetype::ComponentCell, ComponentTicks, Entity, Tick};
+
 pub type ComponentTypeId = usize;
 
 /// A unique identifier for a component type. This is used when deriving the a component to
@@ -21,3 +25,171 @@ pub trait Component {
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
 }
+
+/// Implements `Component` for components defined by the crate itself, which can't use
+/// `#[derive(Component)]` as the generated code refers to the crate by name.
+macro_rules! impl_component {
+    ($($component:ty),* $(,)?) => {
+        $(
+            impl $crate::Component for $component {
+                fn get_type_id() -> usize {
+                    static TYPE_ID: std::sync::OnceLock<usize> = std::sync::OnceLock::new();
+                    *TYPE_ID.get_or_init($crate::get_next_component_type_id)
+                }
+
+                fn as_any(&self) -> &dyn std::any::Any {
+                    self
+                }
+
+                fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+                    self
+                }
+            }
+        )*
+    };
+}
+pub(crate) use impl_component;
+
+/// A handle to the component `C` of an entity, returned by `EntityManager::get_component`.
+///
+/// The handle shares the cell of the component with its archetype column, so it keeps working when
+/// the entity, or any other entity, gains or loses components. Each component is borrowed on its
+/// own: borrowing it doesn't prevent borrowing the same component type of other entities, or
+/// adding components to them. Once the component is removed or replaced, or its entity despawned,
+/// the handle keeps the last value, which is no longer part of the entity.
+///
+/// Mutably borrowing the component marks it as changed.
+pub struct ComponentRef<C> {
+    entity: Entity,
+    cell: Rc<ComponentCell<C>>,
+    change_tick: Rc<Cell<Tick>>,
+}
+
+impl<C: 'static> ComponentRef<C> {
+    pub(crate) fn new(
+        entity: Entity,
+        cell: Rc<ComponentCell<C>>,
+        change_tick: Rc<Cell<Tick>>,
+    ) -> Self {
+        Self { entity, cell, change_tick }
+    }
+}
+
+impl<C> ComponentRef<C> {
+    /// The entity owning the component.
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+
+    /// When the component was added, and when it was last mutably borrowed.
+    pub fn ticks(&self) -> ComponentTicks {
+        self.cell.ticks.get()
+    }
+
+    /// Immutably borrows the component. Panics if the component is currently mutably borrowed.
+    pub fn borrow(&self) -> Ref<'_, C> {
+        self.cell.value.borrow()
+    }
+
+    /// Mutably borrows the component. Panics if the component is currently borrowed.
+    pub fn borrow_mut(&self) -> RefMut<'_, C> {
+        let value = self.cell.value.borrow_mut();
+        self.mark_changed();
+        value
+    }
+
+    /// Immutably borrows the component, returning an error if it is currently mutably borrowed.
+    pub fn try_borrow(&self) -> Result<Ref<'_, C>, BorrowError> {
+        self.cell.value.try_borrow()
+    }
+
+    /// Mutably borrows the component, returning an error if it is currently borrowed.
+    pub fn try_borrow_mut(&self) -> Result<RefMut<'_, C>, BorrowMutError> {
+        let value = self.cell.value.try_borrow_mut()?;
+        self.mark_changed();
+        Ok(value)
+    }
+
+    fn mark_changed(&self) {
+        let ticks = &self.cell.ticks;
+        ticks.set(ComponentTicks { changed: self.change_tick.get(), ..ticks.get() });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::EntityManager;
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+    #[derive(Debug, PartialEq)]
+    struct Shield(u32);
+    impl_component!(Health, Shield);
+
+    #[test]
+    fn handle_follows_its_entity_when_another_entity_leaves_the_archetype() {
+        let em = EntityManager::new();
+        let x = em.create_entity();
+        let y = em.create_entity();
+        em.add_component(x, Health(1));
+        em.add_component(y, Health(2));
+        let health = em.get_component::<Health>(&y).unwrap();
+
+        em.add_component(x, Shield(1));
+
+        assert_eq!(*health.borrow(), Health(2));
+        health.borrow_mut().0 = 3;
+        assert_eq!(*em.get_component::<Health>(&y).unwrap().borrow(), Health(3));
+    }
+
+    #[test]
+    fn handle_follows_its_entity_to_another_archetype() {
+        let em = EntityManager::new();
+        let entity = em.create_entity();
+        em.add_component(entity, Health(1));
+        let health = em.get_component::<Health>(&entity).unwrap();
+
+        em.add_component(entity, Shield(1));
+
+        assert_eq!(*health.borrow(), Health(1));
+        health.borrow_mut().0 = 2;
+        assert_eq!(
+            *em.get_component::<Health>(&entity).unwrap().borrow(),
+            Health(2)
+        );
+    }
+
+    #[test]
+    fn components_of_different_entities_are_borrowed_separately() {
+        let em = EntityManager::new();
+        let x = em.create_entity();
+        let y = em.create_entity();
+        em.add_component(x, Health(1));
+        em.add_component(y, Health(2));
+        let x_health = em.get_component::<Health>(&x).unwrap();
+        let y_health = em.get_component::<Health>(&y).unwrap();
+
+        let x_borrow = x_health.borrow();
+        y_health.borrow_mut().0 = 3;
+
+        assert_eq!(*x_borrow, Health(1));
+        assert!(x_health.try_borrow_mut().is_err());
+        drop(x_borrow);
+        assert_eq!(*y_health.borrow(), Health(3));
+    }
+
+    #[test]
+    fn components_can_be_added_while_others_of_the_same_type_are_borrowed() {
+        let em = EntityManager::new();
+        let x = em.create_entity();
+        let y = em.create_entity();
+        em.add_component(x, Health(1));
+        let x_health = em.get_component::<Health>(&x).unwrap();
+        let x_borrow = x_health.borrow();
+
+        em.add_component(y, Health(2));
+
+        assert_eq!(*x_borrow, Health(1));
+        assert_eq!(*em.get_component::<Health>(&y).unwrap().borrow(), Health(2));
+    }
+}
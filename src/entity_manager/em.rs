@@ -1,17 +1,32 @@
 use std::{
-    any::Any,
-    cell::{Ref, RefCell},
+    cell::{Cell, Ref, RefCell},
     collections::{HashMap, HashSet},
     rc::Rc,
 };
 
-use crate::ComponentSignature;
+use crate::{ComponentSignature, GlobalTransform, Resources, Transform};
 
 use super::{
-    entity::get_next_entity_id, Component, ComponentTypeId, Entity, EntityId, GroupManager,
-    TagManager,
+    archetype::Archetypes,
+    clone::Cloneable,
+    entity::EntityAllocator,
+    entity_builder::{EntityTags, Insert},
+    hooks::{DeferredWorld, Hook, HookKind, HookRegistry},
+    prefab::Prefab,
+    relation::RelationStorage,
+    removed::RemovedComponentsStorage,
+    Bundle, Commands, Component, ComponentRef, ComponentTypeId, Entity, EntityBuilder,
+    GroupManager, Query, QueryFilter, QueryParam, Relation, RemovedComponents, TagManager, Tick,
 };
 
+/// A handle to the entities and their components, shared by the `EntityComponentSystem` and its
+/// systems.
+///
+/// Structural changes made through the manager, such as adding or removing components, are
+/// applied right away, but systems only learn about them at the next sync point of
+/// `EntityComponentSystem::update`: after the running system returns, or at the start of the next
+/// update when the change is made outside of systems. The entity then joins or leaves the systems
+/// whose signature it starts or stops matching.
 #[derive(Clone)]
 pub struct EntityManager {
     pub(crate) inner: Rc<RefCell<EntityManagerInner>>,
@@ -30,202 +45,716 @@ impl EntityManager {
         self.inner.borrow_mut().create_entity()
     }
 
-    pub fn destroy_entity(&self, entity: Entity) {
-        self.inner.borrow_mut().destroy_entity(entity);
+    /// Enqueues the entity to be destroyed in the next update. Returns `false` if the entity is
+    /// not alive.
+    ///
+    /// Until then, the entity stays alive with its components, tag and groups, and the systems
+    /// running later in the current frame still see it. `Commands::destroy_entity` despawns it at
+    /// the next sync point instead, right after the current system.
+    pub fn destroy_entity(&self, entity: Entity) -> bool {
+        self.inner.borrow_mut().destroy_entity(entity)
     }
 
-    pub fn add_entity_to_group(&self, entity: &Entity, group: &str) {
+    /// Enqueues the entity and all of its descendants to be destroyed in the next update, like
+    /// `destroy_entity`. Returns `false` if the entity is not alive.
+    pub fn destroy_recursive(&self, entity: Entity) -> bool {
+        self.inner.borrow_mut().destroy_recursive(entity)
+    }
+
+    /// Makes `parent` the parent of `child`, detaching `child` from its previous parent. Returns
+    /// `false` if either entity is not alive, or if `child` is `parent` or one of its ancestors.
+    ///
+    /// When a parent is despawned through `destroy_entity`, its children are detached and become
+    /// roots. Use `destroy_recursive` to despawn them along with it.
+    pub fn set_parent(&self, child: Entity, parent: Entity) -> bool {
+        self.inner.borrow_mut().set_parent(child, parent)
+    }
+
+    /// Detaches the entity from its parent. Returns the previous parent, if any.
+    pub fn remove_parent(&self, child: Entity) -> Option<Entity> {
+        self.inner.borrow_mut().remove_parent(child)
+    }
+
+    /// The parent of the entity, if any.
+    pub fn parent(&self, entity: Entity) -> Option<Entity> {
+        self.inner.borrow().parent(entity)
+    }
+
+    /// The children of the entity, in the order they were added.
+    pub fn children(&self, entity: Entity) -> Vec<Entity> {
+        self.inner.borrow().children(entity)
+    }
+
+    /// The ancestors of the entity, from its parent up to the root.
+    pub fn ancestors(&self, entity: Entity) -> Vec<Entity> {
+        self.inner.borrow().ancestors(entity)
+    }
+
+    /// The descendants of the entity, depth first.
+    pub fn descendants(&self, entity: Entity) -> Vec<Entity> {
+        self.inner.borrow().descendants(entity)
+    }
+
+    /// Adds the relation `R` from `source` to `target`. Returns `false` if either entity is not
+    /// alive, or if the relation already exists.
+    pub fn add_relation<R: Relation>(&self, source: Entity, target: Entity) -> bool {
+        self.inner.borrow_mut().add_relation::<R>(source, target)
+    }
+
+    /// Removes the relation `R` from `source` to `target`. Returns `false` if it didn't exist.
+    pub fn remove_relation<R: Relation>(&self, source: Entity, target: Entity) -> bool {
+        self.inner.borrow_mut().remove_relation::<R>(source, target)
+    }
+
+    /// Returns `true` if `source` has the relation `R` with `target`.
+    pub fn has_relation<R: Relation>(&self, source: Entity, target: Entity) -> bool {
+        self.inner.borrow().has_relation::<R>(source, target)
+    }
+
+    /// The targets of the relations `R` of `source`, in the order they were added.
+    pub fn targets<R: Relation>(&self, source: Entity) -> Vec<Entity> {
+        self.inner.borrow().targets::<R>(source)
+    }
+
+    /// The first target of the relations `R` of `source`, for relations with a single target such
+    /// as `OwnedBy`.
+    pub fn target<R: Relation>(&self, source: Entity) -> Option<Entity> {
         self.inner
-            .borrow_mut()
-            .group_manager
-            .add_entity_to_group(entity, group);
+            .borrow()
+            .relations
+            .targets::<R>(source)
+            .first()
+            .copied()
+    }
+
+    /// The entities with the relation `R` to `target`, e.g. all the entities targeting an enemy.
+    pub fn sources<R: Relation>(&self, target: Entity) -> Vec<Entity> {
+        self.inner.borrow().sources::<R>(target)
+    }
+
+    /// All the `(source, target)` pairs of the relation `R`.
+    pub fn relation_pairs<R: Relation>(&self) -> Vec<(Entity, Entity)> {
+        self.inner.borrow().relations.pairs::<R>()
+    }
+
+    /// The entities despawned since the start of the current frame, in the order they were
+    /// despawned. Entities destroyed with `destroy_entity` are despawned at the start of the next
+    /// frame, so they show up in that frame.
+    pub fn despawned_this_frame(&self) -> Vec<Entity> {
+        self.inner.borrow().despawned_this_frame().to_vec()
+    }
+
+    /// Returns `true` if the entity was created by this manager and hasn't been despawned yet.
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.inner.borrow().is_alive(entity)
+    }
+
+    /// Adds the entity to `group`. Returns `false` if the entity is not alive.
+    pub fn add_entity_to_group(&self, entity: &Entity, group: &str) -> bool {
+        let mut inner = self.inner.borrow_mut();
+        if !inner.is_alive(*entity) {
+            return false;
+        }
+        inner.group_manager.add_entity_to_group(entity, group);
+        true
+    }
+
+    /// Removes the entity from `group`. Returns `false` if the entity is not alive.
+    pub fn remove_entity_from_group(&self, entity: &Entity, group: &str) -> bool {
+        let mut inner = self.inner.borrow_mut();
+        if !inner.is_alive(*entity) {
+            return false;
+        }
+        inner.group_manager.remove_entity_from_group(entity, group);
+        true
+    }
+
+    /// Sets the unique tag of the entity, which the entity previously tagged with `tag` loses.
+    /// Returns `false` if the entity is not alive.
+    pub fn set_tag(&self, entity: Entity, tag: &str) -> bool {
+        let inner = self.inner.borrow();
+        if !inner.is_alive(entity) {
+            return false;
+        }
+        inner.tag_manager.set_tag(entity, tag);
+        true
     }
 
-    pub fn set_tag(&self, entity: Entity, tag: &str) {
-        self.inner.borrow_mut().tag_manager.set_tag(entity, tag);
+    /// Removes the unique tag of the entity, keeping its labels. Returns `false` if the entity is
+    /// not alive.
+    pub fn remove_tag(&self, entity: Entity) -> bool {
+        let inner = self.inner.borrow();
+        if !inner.is_alive(entity) {
+            return false;
+        }
+        inner.tag_manager.remove_tag(entity);
+        true
+    }
+
+    /// Adds `label` to the labels of the entity. Returns `false` if the entity is not alive.
+    pub fn add_label(&self, entity: Entity, label: &str) -> bool {
+        let inner = self.inner.borrow();
+        if !inner.is_alive(entity) {
+            return false;
+        }
+        inner.tag_manager.add_label(entity, label);
+        true
+    }
+
+    /// Removes `label` from the labels of the entity. Returns `false` if the entity is not alive.
+    pub fn remove_label(&self, entity: Entity, label: &str) -> bool {
+        let inner = self.inner.borrow();
+        if !inner.is_alive(entity) {
+            return false;
+        }
+        inner.tag_manager.remove_label(entity, label);
+        true
+    }
+
+    /// The entities with `tag`, either as their unique tag or as a label.
+    pub fn entities_with_tag(&self, tag: &str) -> Vec<Entity> {
+        self.inner.borrow().tag_manager.entities_with_tag(tag)
+    }
+
+    /// The entities in `group`, in the order they were added.
+    pub fn entities_in_group(&self, group: &str) -> Vec<Entity> {
+        self.inner.borrow().group_manager.entities_in_group(group)
     }
 
+    /// Retrieves the component of type `C` from the entity. Returns `None` if the entity is not
+    /// alive or doesn't have the component.
     pub fn get_component<C: Component + 'static>(
         &self,
         entity: &Entity,
-    ) -> Option<Rc<RefCell<Box<C>>>> {
+    ) -> Option<ComponentRef<C>> {
         self.inner.borrow().get_component::<C>(entity)
     }
 
-    pub fn add_component<C: Component + 'static>(&self, entity: Entity, component: C) {
-        self.inner.borrow_mut().add_component(entity, component);
+    /// Adds the Component `C` to the entity, replacing the existing component of the same type.
+    /// Returns `false` if the entity is not alive.
+    pub fn add_component<C: Component + 'static>(&self, entity: Entity, component: C) -> bool {
+        self.inner.borrow_mut().add_component(entity, component)
+    }
+
+    /// Adds all the components of the bundle to the entity. Returns `false` if the entity is not
+    /// alive.
+    pub fn add_bundle<B: Bundle>(&self, entity: Entity, bundle: B) -> bool {
+        self.inner.borrow_mut().add_bundle(entity, bundle)
+    }
+
+    /// Removes the Component `C` from the entity. The removed value is reported by
+    /// `RemovedComponents`. Returns `false` if the entity is not alive.
+    pub fn remove_component<C: Component + 'static>(&self, entity: Entity) -> bool {
+        self.inner.borrow_mut().remove_component::<C>(entity)
+    }
+
+    /// Returns an `EntityBuilder` creating an entity with all of its components at once.
+    pub fn spawn(&self) -> EntityBuilder {
+        EntityBuilder::new(self)
+    }
+
+    /// Registers the Component `C` as cloneable, so `clone_entity` copies it. `Transform` and
+    /// `GlobalTransform` are registered by default.
+    pub fn register_cloneable<C: Component + Clone + 'static>(&self) {
+        self.inner.borrow_mut().register_cloneable::<C>();
+    }
+
+    /// Creates a copy of the entity with its cloneable components, labels and groups. The copy has
+    /// no parent, children or relations. If `copy_tag` is `true`, the unique tag of the entity
+    /// moves to the copy, and the entity loses it. Returns `None` if the entity is not alive.
+    ///
+    /// Like entities created with `create_entity`, the copy is added to systems in the next update.
+    pub fn clone_entity(&self, entity: Entity, copy_tag: bool) -> Option<Entity> {
+        self.inner.borrow_mut().clone_entity(entity, copy_tag)
+    }
+
+    /// Registers a hook run when the Component `C` is added to an entity that didn't have it, right
+    /// after the component is written. Hooks run while the entity is being updated, so they can't
+    /// use the `EntityManager`. They get the entity, the component, and a `DeferredWorld` to read
+    /// the other components and the resources, and to record further changes with its commands,
+    /// applied at the next sync point.
+    ///
+    /// When several components are written at once, as with a bundle, their hooks run in the
+    /// order of the component type ids.
+    pub fn on_add<C: Component + 'static>(
+        &self,
+        hook: impl Fn(Entity, &mut C, &DeferredWorld) + 'static,
+    ) {
+        let hook: Hook<C> = Rc::new(hook);
+        self.inner.borrow_mut().add_hook(HookKind::Add, hook);
+    }
+
+    /// Registers a hook run each time the Component `C` is written to an entity, either added or
+    /// replaced. Runs after the `on_add` hooks. See `on_add` for what hooks can do.
+    pub fn on_insert<C: Component + 'static>(
+        &self,
+        hook: impl Fn(Entity, &mut C, &DeferredWorld) + 'static,
+    ) {
+        let hook: Hook<C> = Rc::new(hook);
+        self.inner.borrow_mut().add_hook(HookKind::Insert, hook);
+    }
+
+    /// Registers a hook run right before the Component `C` is removed from an entity, including
+    /// when the entity is despawned. See `on_add` for what hooks can do.
+    pub fn on_remove<C: Component + 'static>(
+        &self,
+        hook: impl Fn(Entity, &mut C, &DeferredWorld) + 'static,
+    ) {
+        let hook: Hook<C> = Rc::new(hook);
+        self.inner.borrow_mut().add_hook(HookKind::Remove, hook);
+    }
+
+    /// Registers `prefab` under `name`, replacing the prefab previously registered with that name.
+    pub fn register_prefab(&self, name: &str, prefab: Prefab) {
+        self.inner.borrow_mut().register_prefab(name, prefab);
+    }
+
+    /// Returns an `EntityBuilder` creating an instance of the prefab named `name`, or `None` if no
+    /// prefab is registered with that name. Components and the tag added to the builder override
+    /// the ones of the prefab, and groups are added to its groups. The children of the prefab are
+    /// instantiated along with the entity.
+    pub fn instantiate(&self, name: &str) -> Option<EntityBuilder> {
+        let prefab = self.inner.borrow().prefab(name)?;
+        Some(EntityBuilder::from_prefab(self, name, &prefab))
+    }
+
+    pub fn get_entities_with_signature(&self, signature: &ComponentSignature) -> Vec<Entity> {
+        self.inner.borrow().get_entities_with_signature(signature)
     }
 
     pub fn tag_manager(&self) -> TagManager {
         self.inner.borrow().tag_manager.clone()
     }
 
-    pub fn group_manager(&self) -> GroupManager {
-        self.inner.borrow().group_manager.clone()
+    /// The groups of the entities. The `EntityManager` stays borrowed until the returned reference
+    /// is dropped, so it shouldn't be held while changing entities.
+    pub fn group_manager(&self) -> Ref<'_, GroupManager> {
+        Ref::map(self.inner.borrow(), |inner| &inner.group_manager)
+    }
+
+    /// The buffer of structural changes applied at the sync points of
+    /// `EntityComponentSystem::update`.
+    pub fn commands(&self) -> Commands {
+        self.inner.borrow().commands.clone()
     }
 
-    // pub fn create_query<T>(&self) -> Query<T> {
-    //     Query::new(self)
-    // }
+    /// The tick components mutated right now are stamped with.
+    pub fn change_tick(&self) -> Tick {
+        self.inner.borrow().change_tick()
+    }
+
+    /// The tick of the previous run of the system currently running, or `0` outside of systems.
+    /// Components whose ticks are newer were added or changed since that run.
+    pub fn last_run(&self) -> Tick {
+        self.inner.borrow().last_run()
+    }
+
+    /// Creates a `Query` over the entities that have the components in `T`.
+    pub fn query<T: QueryParam>(&self) -> Query<T> {
+        Query::new(self)
+    }
+
+    /// Creates a `Query` over the entities that have the components in `T` and pass the filter
+    /// `F`, for example `em.query_filtered::<&Transform, Without<CameraFollowComponent>>()`.
+    pub fn query_filtered<T: QueryParam, F: QueryFilter>(&self) -> Query<T, F> {
+        Query::new(self)
+    }
+
+    /// Creates a `RemovedComponents` stream over the components of type `C` removed from entities.
+    pub fn removed<C: Component + 'static>(&self) -> RemovedComponents<C> {
+        RemovedComponents::new(self)
+    }
 }
 
 pub struct EntityManagerInner {
-    pub(crate) components: HashMap<ComponentTypeId, HashMap<EntityId, Rc<dyn Any>>>,
-    pub(crate) entities: HashMap<EntityId, Entity>,
+    // Shared with `Commands`, which reserve the entities they spawn.
+    pub(crate) allocator: Rc<RefCell<EntityAllocator>>,
+    // Shared with `ComponentRef` handles, which stamp the components they mutably borrow.
+    change_tick: Rc<Cell<Tick>>,
+    // The tick of the last run of the system currently running.
+    last_run: Tick,
+    pub(crate) archetypes: Archetypes,
+    pub(crate) removed: RemovedComponentsStorage,
     pub(crate) entities_to_spawn: HashSet<Entity>,
     pub(crate) entities_to_despawn: HashSet<Entity>,
-    pub(crate) entity_component_signatures: HashMap<EntityId, ComponentSignature>,
-    tag_manager: TagManager,
-    group_manager: GroupManager,
+    // The entities despawned since the start of the current frame.
+    despawned: Vec<Entity>,
+    // Entities whose components were added or removed, or that were despawned, since the system
+    // membership was last updated.
+    touched: HashSet<Entity>,
+    pub(crate) tag_manager: TagManager,
+    pub(crate) group_manager: GroupManager,
+    pub(crate) relations: RelationStorage,
+    pub(crate) prefabs: HashMap<String, Rc<Prefab>>,
+    // The components copied by `clone_entity`.
+    pub(crate) cloneable: HashMap<ComponentTypeId, Cloneable>,
+    // The hooks run when components are added, written or removed.
+    pub(crate) hooks: HookRegistry,
+    pub(crate) commands: Commands,
+    // Shared with the `EntityComponentSystem`, so hooks can read them.
+    pub(crate) resources: Rc<RefCell<Resources>>,
 }
 
 impl EntityManagerInner {
     pub fn new() -> Self {
-        EntityManagerInner {
-            components: HashMap::new(),
-            entities: HashMap::new(),
+        let allocator = Rc::new(RefCell::new(EntityAllocator::default()));
+        let mut inner = EntityManagerInner {
+            commands: Commands::new(allocator.clone()),
+            allocator,
+            change_tick: Rc::new(Cell::new(1)),
+            last_run: 0,
+            archetypes: Archetypes::default(),
+            removed: RemovedComponentsStorage::default(),
             entities_to_spawn: HashSet::new(),
             entities_to_despawn: HashSet::new(),
-            entity_component_signatures: HashMap::new(),
+            despawned: Vec::new(),
+            touched: HashSet::new(),
             tag_manager: Default::default(),
             group_manager: Default::default(),
-        }
+            relations: Default::default(),
+            prefabs: HashMap::new(),
+            cloneable: HashMap::new(),
+            hooks: HookRegistry::default(),
+            resources: Default::default(),
+        };
+        inner.register_cloneable::<Transform>();
+        inner.register_cloneable::<GlobalTransform>();
+        inner
     }
 
     pub fn update(&mut self) {
-        // Add entities waiting to be created to systems.
-        for entity in &mut self.entities_to_spawn.drain() {
-            self.entities.insert(entity.id(), entity);
-        }
-
-        // Despawn entities waiting to be killed from systems.
-        for entity in &mut self.entities_to_despawn.drain() {
-            // Remove Signature.
-            self.entity_component_signatures.remove(&entity.id());
+        // Entities waiting to be created are already stored, they only become visible to systems.
+        self.entities_to_spawn.clear();
+        self.despawned.clear();
 
-            // Remove the components...
-            for v in &mut self.components.values_mut() {
-                v.remove(&entity.id());
-            }
+        self.removed.begin_frame(self.change_tick.get());
 
-            // Remove entity.
-            self.entities.remove(&entity.id());
+        // Despawn entities waiting to be killed from systems.
+        let entities_to_despawn: Vec<Entity> = self.entities_to_despawn.drain().collect();
+        for entity in entities_to_despawn {
+            self.despawn(entity);
         }
     }
 
     /// Creates a new entity and enqueues it to be added in the next update.
     pub fn create_entity(&mut self) -> Entity {
-        let id: EntityId = get_next_entity_id();
-        let entity = Entity::new(id);
+        let entity = self.allocator.borrow_mut().allocate();
+        self.archetypes.insert_entity(entity);
         self.entities_to_spawn.insert(entity);
         entity
     }
 
+    /// Creates a new entity, running `inserts` to write its components to the archetype for
+    /// `signature`, and giving it `tags`. Like `create_entity`, the entity is enqueued to be added
+    /// in the next update.
+    pub(crate) fn create_entity_with(
+        &mut self,
+        signature: &ComponentSignature,
+        inserts: Vec<Insert>,
+        tags: &EntityTags,
+    ) -> Entity {
+        let entity = self.create_entity();
+        self.insert_with(entity, signature, inserts, tags);
+        entity
+    }
+
+    /// Moves an entity without components to the archetype for `signature`, running `inserts` to
+    /// write its components, and gives it `tags`, before running the hooks of the components.
+    pub(crate) fn insert_with(
+        &mut self,
+        entity: Entity,
+        signature: &ComponentSignature,
+        inserts: Vec<Insert>,
+        tags: &EntityTags,
+    ) {
+        let dst = self.archetypes.get_or_insert(signature);
+        let tick = self.change_tick.get();
+        let location = self
+            .archetypes
+            .move_entity(entity, dst, tick, &mut self.removed);
+        let archetype = self.archetypes.get_mut(dst);
+        for insert in inserts {
+            insert(archetype, location.row, tick);
+        }
+        self.touched.insert(entity);
+        if let Some(tag) = &tags.tag {
+            self.tag_manager.set_tag(entity, tag);
+        }
+        for label in &tags.labels {
+            self.tag_manager.add_label(entity, label);
+        }
+        for group in &tags.groups {
+            self.group_manager.add_entity_to_group(&entity, group);
+        }
+
+        let inserted = |type_id| signature.has_component_type(type_id);
+        self.run_hooks(HookKind::Add, entity, inserted);
+        self.run_hooks(HookKind::Insert, entity, inserted);
+    }
+
+    /// Spawns an entity reserved by `Commands`.
+    pub(crate) fn spawn_reserved(&mut self, entity: Entity) {
+        self.allocator.borrow_mut().activate(entity);
+        self.archetypes.insert_entity(entity);
+        self.touched.insert(entity);
+    }
+
+    /// Returns `true` if the entity has the tags and groups required by `signature`.
+    pub(crate) fn matches_tags_and_groups(
+        &self,
+        signature: &ComponentSignature,
+        entity: Entity,
+    ) -> bool {
+        signature.matches_tags_and_groups(entity, &self.tag_manager, &self.group_manager)
+    }
+
+    /// Returns `true` if lookups such as queries, whose component signature matches the archetype
+    /// of the entity, yield the entity: it must have the tags and groups required by `signature`,
+    /// and not be waiting for the next update to be added.
+    pub(crate) fn is_matched_by(&self, signature: &ComponentSignature, entity: Entity) -> bool {
+        !self.entities_to_spawn.contains(&entity) && self.matches_tags_and_groups(signature, entity)
+    }
+
+    /// Takes the entities whose system membership may have changed since the last call, because
+    /// their components, tags or groups changed.
+    pub(crate) fn take_touched(&mut self) -> HashSet<Entity> {
+        let mut touched = std::mem::take(&mut self.touched);
+        touched.extend(self.tag_manager.take_changed());
+        touched.extend(self.group_manager.take_changed());
+        touched
+    }
+
+    /// Removes the entity right away, along with its components, hierarchy, tag, groups and
+    /// relations, and releases its slot to be reused. Systems are told at the next sync point.
+    /// Returns `false` if the entity is not alive.
+    pub(crate) fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+
+        self.detach_despawned(entity);
+        self.run_hooks(HookKind::Remove, entity, |_| true);
+        let tick = self.change_tick.get();
+        self.archetypes
+            .remove_entity(entity, tick, &mut self.removed);
+        self.allocator.borrow_mut().free(entity);
+        self.tag_manager.remove_entity(entity);
+        self.group_manager.remove_entity(&entity);
+        self.entities_to_spawn.remove(&entity);
+        self.entities_to_despawn.remove(&entity);
+        self.touched.insert(entity);
+        self.despawned.push(entity);
+        self.cleanup_relations(entity);
+        true
+    }
+
+    pub fn despawned_this_frame(&self) -> &[Entity] {
+        &self.despawned
+    }
+
+    pub fn change_tick(&self) -> Tick {
+        self.change_tick.get()
+    }
+
+    pub fn last_run(&self) -> Tick {
+        self.last_run
+    }
+
+    /// Makes queries report the changes made after `last_run`, the tick of the previous run of
+    /// the system about to run. Advances the change tick, so the changes made by the system are
+    /// newer than the run and are reported to the system in its next run as well. Returns the
+    /// tick of the run.
+    pub(crate) fn begin_system_run(&mut self, last_run: Tick) -> Tick {
+        self.last_run = last_run;
+        let this_run = self.change_tick.get();
+        self.change_tick.set(this_run + 1);
+        this_run
+    }
+
+    /// Makes queries report every change again, as outside of systems.
+    pub(crate) fn end_system_run(&mut self) {
+        self.last_run = 0;
+    }
+
     /// Enqueues an entity to be destroyed in the next update.
-    pub fn destroy_entity(&mut self, entity: Entity) {
-        // Remove components...
+    pub fn destroy_entity(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
         self.entities_to_despawn.insert(entity);
+        true
     }
 
-    /// Adds the Component `C` to the entity.
-    pub fn add_component<C: Component + 'static>(&mut self, entity: Entity, component: C) {
-        let component_type_id = C::get_type_id();
-        let component = Rc::new(RefCell::new(Box::new(component)));
+    /// An entity is alive from the moment it is created until its despawn is applied in
+    /// `update`. Handles to despawned entities never become alive again.
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.allocator.borrow().is_alive(entity)
+    }
 
-        // Update the entity signature to indicate that the entity has the component.
-        self.entity_component_signatures
-            .entry(entity.id())
-            .or_default()
-            .require_component::<C>();
+    /// Adds the Component `C` to the entity, replacing the existing component of the same type.
+    pub fn add_component<C: Component + 'static>(&mut self, entity: Entity, component: C) -> bool {
+        self.add_bundle(entity, component)
+    }
+
+    /// Adds the components of the bundle to the entity, replacing the existing components of the
+    /// same types. The entity moves to its new archetype once for the whole bundle.
+    pub fn add_bundle<B: Bundle>(&mut self, entity: Entity, bundle: B) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
 
-        // Add the component to the component storage.
-        self.components
-            .entry(component_type_id)
-            .or_default()
-            .insert(entity.id(), component);
+        // Move the entity to the archetype that includes the components, if it doesn't already
+        // have all of them, then write the components to their columns.
+        let location = self.archetypes.location(entity.id()).unwrap();
+        let previous = self.archetypes.get(location.archetype).signature().clone();
+        let mut inserted = ComponentSignature::default();
+        B::add_to_signature(&mut inserted);
+        let mut signature = previous.clone();
+        B::add_to_signature(&mut signature);
+        let dst = self.archetypes.get_or_insert(&signature);
+        if dst != location.archetype {
+            self.touched.insert(entity);
+        }
+        let tick = self.change_tick.get();
+        let location = self
+            .archetypes
+            .move_entity(entity, dst, tick, &mut self.removed);
+        bundle.insert_into(self.archetypes.get_mut(dst), location.row, tick);
+
+        self.run_hooks(HookKind::Add, entity, |type_id| {
+            inserted.has_component_type(type_id) && !previous.has_component_type(type_id)
+        });
+        self.run_hooks(HookKind::Insert, entity, |type_id| {
+            inserted.has_component_type(type_id)
+        });
+        true
     }
 
     /// Retrieves the component of type `C` from the entity, if available.
     pub fn get_component<C: Component + 'static>(
         &self,
         entity: &Entity,
-    ) -> Option<Rc<RefCell<Box<C>>>> {
-        let component_type_id = C::get_type_id();
-        let entity_id = entity.id();
-        let components = self.components.get(&component_type_id)?;
-        let component = components.get(&entity_id)?.clone();
-        let component: Rc<RefCell<Box<C>>> = component.downcast().unwrap();
-        Some(component)
+    ) -> Option<ComponentRef<C>> {
+        if !self.is_alive(*entity) {
+            return None;
+        }
+
+        let location = self.archetypes.location(entity.id())?;
+        let column = self
+            .archetypes
+            .get(location.archetype)
+            .column::<C>(C::get_type_id())?;
+        Some(ComponentRef::new(
+            *entity,
+            column.cells[location.row].clone(),
+            self.change_tick.clone(),
+        ))
     }
 
     /// Removes the Component `C` from the entity.
-    pub fn remove_component<C: Component + 'static>(&mut self, entity: Entity) {
-        let component_type_id = C::get_type_id();
-        if let Some(component) = self.components.get_mut(&component_type_id) {
-            component.remove(&entity.id());
+    pub fn remove_component<C: Component + 'static>(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
         }
 
-        if let Some(signature) = self.entity_component_signatures.get_mut(&entity.id()) {
-            signature.remove_component::<C>();
+        let location = self.archetypes.location(entity.id()).unwrap();
+        let mut signature = self.archetypes.get(location.archetype).signature().clone();
+        if !signature.has_component::<C>() {
+            return true;
         }
-    }
-
-    pub fn query<C: Component + 'static>(&self) -> Vec<Ref<C>> {
-        let component_type_id = C::get_type_id();
-        let components = self.components.get(&component_type_id).unwrap();
-        components
-            .iter()
-            .map(|(_, c)| {
-                (c as &dyn Any)
-                    .downcast_ref::<RefCell<C>>()
-                    .unwrap()
-                    .borrow()
-            })
-            .collect()
-    }
 
-    pub fn query_mut<C: Component + 'static>(&mut self) -> Vec<&RefCell<C>> {
-        let component_type_id = C::get_type_id();
-        let components = self.components.get_mut(&component_type_id).unwrap();
-        components
-            .iter()
-            .map(|(_, c)| (c as &dyn Any).downcast_ref::<RefCell<C>>().unwrap())
-            .collect()
+        self.run_hooks(HookKind::Remove, entity, |type_id| {
+            type_id == C::get_type_id()
+        });
+
+        // Moving the entity to the archetype without the component moves the component to the
+        // removed components.
+        signature.remove_component::<C>();
+        let dst = self.archetypes.get_or_insert(&signature);
+        let tick = self.change_tick.get();
+        self.archetypes
+            .move_entity(entity, dst, tick, &mut self.removed);
+        self.touched.insert(entity);
+        true
     }
 
     pub fn get_entities_with_signature(&self, signature: &ComponentSignature) -> Vec<Entity> {
-        self.entities
-            .values()
-            .filter(|e| {
-                if let Some(s) = self.entity_component_signatures.get(&e.id()) {
-                    signature.is_subset(s)
-                } else {
-                    false
-                }
-            })
-            .cloned()
+        self.archetypes
+            .iter()
+            .filter(|archetype| signature.matches(archetype.signature()))
+            .flat_map(|archetype| archetype.entities().borrow().clone())
+            .filter(|entity| self.is_matched_by(signature, *entity))
             .collect()
     }
 
     pub fn get_signature(&self, entity: Entity) -> Option<&ComponentSignature> {
-        self.entity_component_signatures.get(&entity.id())
+        if !self.is_alive(entity) {
+            return None;
+        }
+        let location = self.archetypes.location(entity.id())?;
+        Some(self.archetypes.get(location.archetype).signature())
     }
+}
 
-    pub fn tag_manager(&self) -> TagManager {
-        self.tag_manager.clone()
+impl Default for EntityManager {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    pub fn group_manager(&self) -> &GroupManager {
-        &self.group_manager
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::EntityComponentSystem;
+
+    fn assert_forgotten(em: &EntityManager, entity: Entity) {
+        assert!(!em.is_alive(entity));
+        assert_eq!(em.tag_manager().get_entity("player"), None);
+        assert_eq!(em.tag_manager().get_tag(entity), None);
+        assert!(em.tag_manager().labels_of(entity).is_empty());
+        assert!(em.entities_with_tag("hero").is_empty());
+        assert!(em.group_manager().groups_of(&entity).is_empty());
+        assert!(em.entities_in_group("allies").is_empty());
     }
 
-    pub fn group_manager_mut(&mut self) -> &mut GroupManager {
-        &mut self.group_manager
+    #[test]
+    fn despawning_forgets_the_tag_labels_and_groups() {
+        let ecs = EntityComponentSystem::new();
+        let em = ecs.entity_manager();
+        let spawn = || em.spawn().tag("player").label("hero").group("allies").id();
+
+        let destroyed = spawn();
+        ecs.update(Duration::ZERO);
+        em.destroy_entity(destroyed);
+        assert_eq!(em.tag_manager().get_entity("player"), Some(destroyed));
+        ecs.update(Duration::ZERO);
+        assert_forgotten(&em, destroyed);
+
+        let commanded = spawn();
+        ecs.update(Duration::ZERO);
+        em.commands().destroy_entity(commanded);
+        ecs.update(Duration::ZERO);
+        assert_forgotten(&em, commanded);
     }
-}
 
-impl Default for EntityManager {
-    fn default() -> Self {
-        Self::new()
+    #[test]
+    fn despawned_entities_are_reported_for_one_frame() {
+        let ecs = EntityComponentSystem::new();
+        let em = ecs.entity_manager();
+        let destroyed = em.create_entity();
+        let commanded = em.create_entity();
+        ecs.update(Duration::ZERO);
+
+        em.destroy_entity(destroyed);
+        em.commands().destroy_entity(commanded);
+        assert!(em.despawned_this_frame().is_empty());
+
+        ecs.update(Duration::ZERO);
+        assert_eq!(em.despawned_this_frame(), vec![destroyed, commanded]);
+
+        ecs.update(Duration::ZERO);
+        assert!(em.despawned_this_frame().is_empty());
     }
 }
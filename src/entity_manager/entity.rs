@@ -1,23 +1,148 @@
-use std::sync::atomic::{AtomicUsize, Ordering};
-
 pub type EntityId = usize;
+pub type Generation = u32;
 
-pub(super) fn get_next_entity_id() -> usize {
-    static NEXT_ENTITY_ID: AtomicUsize = AtomicUsize::new(0);
-    NEXT_ENTITY_ID.fetch_add(1, Ordering::Relaxed)
-}
-
+/// A handle to an entity. The handle is made of the entity index and the generation of the slot
+/// the index points to. Once an entity is destroyed its handle is no longer alive, and every
+/// `EntityManager` accessor rejects it, even if the index is later handed out again.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Entity {
     id: EntityId,
+    generation: Generation,
 }
 
 impl Entity {
-    pub fn new(id: EntityId) -> Self {
-        Entity { id }
+    pub fn new(id: EntityId, generation: Generation) -> Self {
+        Entity { id, generation }
     }
 
     pub fn id(&self) -> EntityId {
         self.id
     }
+
+    pub fn generation(&self) -> Generation {
+        self.generation
+    }
+}
+
+/// Hands out entity handles for a single `EntityManager`. Indices of freed entities are recycled
+/// through a free list, and the generation of their slot is bumped so that handles to the freed
+/// entity are no longer alive.
+#[derive(Default)]
+pub(crate) struct EntityAllocator {
+    slots: Vec<EntitySlot>,
+    free_list: Vec<EntityId>,
+}
+
+struct EntitySlot {
+    generation: Generation,
+    alive: bool,
+}
+
+impl EntityAllocator {
+    pub fn allocate(&mut self) -> Entity {
+        let entity = self.reserve();
+        self.activate(entity);
+        entity
+    }
+
+    /// Hands out an entity handle that is not alive until `activate` is called. Used by
+    /// `Commands`, which return the handle of entities that are only spawned on the next flush.
+    pub fn reserve(&mut self) -> Entity {
+        if let Some(id) = self.free_list.pop() {
+            return Entity::new(id, self.slots[id].generation);
+        }
+
+        let id = self.slots.len();
+        self.slots.push(EntitySlot { generation: 0, alive: false });
+        Entity::new(id, 0)
+    }
+
+    /// Makes a reserved entity alive.
+    pub fn activate(&mut self, entity: Entity) {
+        let slot = &mut self.slots[entity.id()];
+        debug_assert_eq!(slot.generation, entity.generation());
+        slot.alive = true;
+    }
+
+    /// Frees the entity slot. Returns `false` if the entity was not alive.
+    pub fn free(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+
+        let slot = &mut self.slots[entity.id()];
+        slot.generation = slot.generation.wrapping_add(1);
+        slot.alive = false;
+        self.free_list.push(entity.id());
+        true
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        match self.slots.get(entity.id()) {
+            Some(slot) => slot.alive && slot.generation == entity.generation(),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn freed_ids_are_recycled_with_a_new_generation() {
+        let mut allocator = EntityAllocator::default();
+        let a = allocator.allocate();
+        let b = allocator.allocate();
+
+        assert!(allocator.free(a));
+        let c = allocator.allocate();
+
+        assert_eq!(c.id(), a.id());
+        assert_eq!(c.generation(), a.generation() + 1);
+        assert!(!allocator.is_alive(a));
+        assert!(allocator.is_alive(b));
+        assert!(allocator.is_alive(c));
+    }
+
+    #[test]
+    fn ids_are_recycled_most_recently_freed_first() {
+        let mut allocator = EntityAllocator::default();
+        let entities: Vec<Entity> = (0..3).map(|_| allocator.allocate()).collect();
+        allocator.free(entities[0]);
+        allocator.free(entities[2]);
+
+        assert_eq!(allocator.allocate().id(), entities[2].id());
+        assert_eq!(allocator.allocate().id(), entities[0].id());
+        assert_eq!(allocator.allocate().id(), 3);
+    }
+
+    #[test]
+    fn freeing_a_dead_entity_does_nothing() {
+        let mut allocator = EntityAllocator::default();
+        let a = allocator.allocate();
+        allocator.free(a);
+        let b = allocator.allocate();
+
+        assert!(!allocator.free(a));
+        assert!(allocator.is_alive(b));
+        assert_eq!(allocator.allocate().id(), 1);
+    }
+
+    #[test]
+    fn reserved_entities_are_alive_once_activated() {
+        let mut allocator = EntityAllocator::default();
+        let entity = allocator.reserve();
+
+        assert!(!allocator.is_alive(entity));
+        assert!(!allocator.free(entity));
+        allocator.activate(entity);
+        assert!(allocator.is_alive(entity));
+    }
+
+    #[test]
+    fn handles_from_other_allocators_are_not_alive() {
+        let allocator = EntityAllocator::default();
+        assert!(!allocator.is_alive(Entity::new(0, 0)));
+    }
 }
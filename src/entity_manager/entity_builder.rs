@@ -0,0 +1,294 @@
+use std::fmt;
+
+use crate::component_signature::ComponentSignature;
+
+use super::{archetype::Archetype, Bundle, Entity, EntityManager, Prefab, TagManager, Tick};
+
+/// Writes a component of a new entity to its archetype, at the row of the entity.
+pub(crate) type Insert = Box<dyn FnOnce(&mut Archetype, usize, Tick)>;
+
+/// The tag, labels and groups of a new entity. They are applied along with its components, before
+/// the hooks of the components run.
+#[derive(Clone, Default)]
+pub(crate) struct EntityTags {
+    pub tag: Option<String>,
+    pub labels: Vec<String>,
+    pub groups: Vec<String>,
+}
+
+impl EntityTags {
+    pub fn is_empty(&self) -> bool {
+        self.tag.is_none() && self.labels.is_empty() && self.groups.is_empty()
+    }
+}
+
+/// Why an `EntityBuilder` couldn't create its entity.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpawnError {
+    /// Another builder that hasn't created its entity yet sets the same unique tag, so only one of
+    /// them can keep it.
+    TagPending { tag: String },
+    /// The builder has no components, tag, labels, groups or children. Entities without anything
+    /// are created with `EntityManager::create_entity`.
+    Empty,
+}
+
+impl fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TagPending { tag } => {
+                write!(f, "the tag {tag:?} is already set by another EntityBuilder")
+            }
+            Self::Empty => write!(f, "the EntityBuilder has nothing to spawn"),
+        }
+    }
+}
+
+impl std::error::Error for SpawnError {}
+
+/// Builds an entity with its components, tag, labels and groups, returned by `EntityManager::spawn` and
+/// `EntityComponentSystem::spawn`:
+///
+/// ```ignore
+/// let tank = ecs
+///     .spawn()
+///     .with(Transform::from_translation(Vec2::ZERO))
+///     .with(HealthComponent { health: 100 })
+///     .group("enemy")
+///     .id();
+/// ```
+///
+/// Nothing is created until `id` or `try_id` is called, and dropping the builder discards it. The
+/// entity is then created with all of its components, tag, labels and groups at once, so its
+/// component hooks see it complete, and like any newly created entity it is added to systems in
+/// the next update, so systems never see it half built.
+#[must_use = "the entity is only created when `id` is called"]
+pub struct EntityBuilder {
+    em: EntityManager,
+    tag_manager: TagManager,
+    signature: ComponentSignature,
+    inserts: Vec<Insert>,
+    tags: EntityTags,
+    // The prefab the entity is an instance of, and the prefabs instantiated as its children.
+    prefab: Option<String>,
+    children: Vec<String>,
+    // The first problem found while building, reported when the entity is created.
+    error: Option<SpawnError>,
+}
+
+impl EntityBuilder {
+    pub(crate) fn new(em: &EntityManager) -> Self {
+        Self {
+            em: em.clone(),
+            tag_manager: em.tag_manager(),
+            signature: ComponentSignature::default(),
+            inserts: Vec::new(),
+            tags: EntityTags::default(),
+            prefab: None,
+            children: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub(crate) fn from_prefab(em: &EntityManager, name: &str, prefab: &Prefab) -> Self {
+        let mut builder = Self::new(em);
+        builder.signature = prefab.signature.clone();
+        builder.inserts = prefab.inserts();
+        builder.tags.labels = prefab.tags.labels.clone();
+        builder.tags.groups = prefab.tags.groups.clone();
+        builder.prefab = Some(name.to_string());
+        builder.children = prefab.children.clone();
+        if let Some(tag) = &prefab.tags.tag {
+            builder.set_tag(tag);
+        }
+        builder
+    }
+
+    /// Adds a component, or all the components of a bundle. Adding a component type twice keeps
+    /// the last one.
+    pub fn with<B: Bundle>(mut self, bundle: B) -> Self {
+        B::add_to_signature(&mut self.signature);
+        self.inserts.push(Box::new(move |archetype, row, tick| {
+            bundle.insert_into(archetype, row, tick)
+        }));
+        self
+    }
+
+    /// Sets the unique tag of the entity. Like `EntityManager::set_tag`, the entity previously
+    /// tagged with `tag` loses it when the entity is created. Setting a tag that another builder
+    /// sets as well makes `try_id` fail.
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.set_tag(tag);
+        self
+    }
+
+    /// Adds `label` to the labels of the entity. Can be called several times to add several
+    /// labels.
+    pub fn label(mut self, label: &str) -> Self {
+        self.tags.labels.push(label.to_string());
+        self
+    }
+
+    /// Adds the entity to `group`. Can be called several times to add the entity to several
+    /// groups.
+    pub fn group(mut self, group: &str) -> Self {
+        self.tags.groups.push(group.to_string());
+        self
+    }
+
+    /// Creates the entity and returns it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the entity can't be created, for the reasons returned by `try_id`.
+    pub fn id(self) -> Entity {
+        self.try_id().unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Creates the entity and returns it, or returns why it can't be created, in which case
+    /// nothing is created.
+    pub fn try_id(mut self) -> Result<Entity, SpawnError> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        if self.inserts.is_empty() && self.tags.is_empty() && self.children.is_empty() {
+            return Err(SpawnError::Empty);
+        }
+
+        let mut inner = self.em.inner.borrow_mut();
+        let entity = inner.create_entity_with(
+            &self.signature,
+            std::mem::take(&mut self.inserts),
+            &self.tags,
+        );
+        if !self.children.is_empty() {
+            let mut chain = self.prefab.take().into_iter().collect();
+            inner.instantiate_children(entity, &self.children, &mut chain);
+        }
+        Ok(entity)
+    }
+
+    fn set_tag(&mut self, tag: &str) {
+        self.release_tag();
+        if self.tag_manager.reserve_pending(tag) {
+            self.tags.tag = Some(tag.to_string());
+        } else {
+            self.error
+                .get_or_insert(SpawnError::TagPending { tag: tag.to_string() });
+        }
+    }
+
+    fn release_tag(&mut self) {
+        if let Some(tag) = self.tags.tag.take() {
+            self.tag_manager.release_pending(&tag);
+        }
+    }
+}
+
+impl Drop for EntityBuilder {
+    fn drop(&mut self) {
+        self.release_tag();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::entity_manager::impl_component;
+
+    struct Health;
+    impl_component!(Health);
+
+    #[test]
+    fn tag_is_taken_from_the_entity_that_had_it() {
+        let em = EntityManager::new();
+        let old = em.spawn().tag("player").id();
+        let new = em.spawn().tag("player").id();
+
+        assert_eq!(em.tag_manager().get_entity("player"), Some(new));
+        assert_eq!(em.tag_manager().get_tag(old), None);
+    }
+
+    #[test]
+    fn tag_is_taken_from_an_entity_waiting_to_be_despawned() {
+        let em = EntityManager::new();
+        let old = em.spawn().tag("player").id();
+        em.destroy_entity(old);
+
+        em.spawn().tag("player").id();
+        em.update();
+
+        let new = em.tag_manager().get_entity("player").unwrap();
+        assert_ne!(new, old);
+        assert!(em.is_alive(new));
+    }
+
+    #[test]
+    fn dropping_a_builder_creates_nothing() {
+        let em = EntityManager::new();
+        let builder = em.spawn().with(Health).tag("player").group("allies");
+
+        // Dropping doesn't touch the manager, so it can be borrowed meanwhile.
+        let groups = em.group_manager();
+        drop(builder);
+        drop(groups);
+        em.update();
+
+        assert_eq!(em.tag_manager().get_entity("player"), None);
+        assert!(em.group_manager().entities_in_group("allies").is_empty());
+        // The tag is free again.
+        assert!(em.spawn().tag("player").try_id().is_ok());
+    }
+
+    #[test]
+    fn tag_pending_on_another_builder_is_rejected() {
+        let em = EntityManager::new();
+        let first = em.spawn().tag("player");
+        let second = em.spawn().with(Health).tag("player");
+
+        assert_eq!(
+            second.try_id(),
+            Err(SpawnError::TagPending { tag: "player".to_string() })
+        );
+        let first = first.id();
+        assert_eq!(em.tag_manager().get_entity("player"), Some(first));
+    }
+
+    #[test]
+    fn empty_builders_are_rejected() {
+        let em = EntityManager::new();
+        assert_eq!(em.spawn().try_id(), Err(SpawnError::Empty));
+    }
+
+    #[test]
+    fn hooks_see_the_tag_labels_and_groups() {
+        let em = EntityManager::new();
+        let seen = Rc::new(RefCell::new(None));
+        let hook_seen = seen.clone();
+        em.on_add::<Health>(move |entity, _, world| {
+            *hook_seen.borrow_mut() = Some((
+                world.tag_manager().get_tag(entity),
+                world.tag_manager().labels_of(entity),
+                world.group_manager().groups_of(&entity),
+            ));
+        });
+
+        em.spawn()
+            .with(Health)
+            .tag("player")
+            .label("flammable")
+            .group("allies")
+            .id();
+
+        assert_eq!(
+            *seen.borrow(),
+            Some((
+                Some("player".to_string()),
+                vec!["flammable".to_string()],
+                vec!["allies".to_string()]
+            ))
+        );
+    }
+}
@@ -1,91 +1,104 @@
-use std::{
-    cell::RefCell,
-    collections::{HashMap, HashSet},
-    rc::Rc,
-};
+use std::collections::{HashMap, HashSet};
 
-use super::{Entity, EntityId};
+use super::Entity;
 
-#[derive(Clone, Default)]
+/// The groups of the entities, stored along with the entities by the `EntityManager`, and changed
+/// through its methods such as `EntityManager::add_entity_to_group`. An entity can be in any
+/// number of groups.
+#[derive(Default)]
 pub struct GroupManager {
-    inner: Rc<RefCell<GroupManagerInner>>,
+    entity_groups: HashMap<Entity, HashSet<String>>,
+    // The entities of each group, in the order they were added.
+    group_entities: HashMap<String, Vec<Entity>>,
+    // The entities whose groups changed, whose system membership must be updated.
+    changed: HashSet<Entity>,
 }
 
 impl GroupManager {
-    pub fn add_entity_to_group(&self, entity: &Entity, group: &str) {
-        self.inner.borrow_mut().add_entity_to_group(entity, group);
-    }
-
-    pub fn remove_entity_from_group(&self, entity: &Entity, group: &str) {
-        self.inner
-            .borrow_mut()
-            .remove_entity_from_group(entity, group);
-    }
-
-    pub fn remove_entity(&self, entity: &Entity) {
-        self.inner.borrow_mut().remove_entity(entity);
-    }
-
-    pub fn group_contains_entity(&self, group: &str, entity: &Entity) -> bool {
-        self.inner.borrow().group_contains_entity(group, entity)
-    }
-
-    pub fn entity_in_group(&self, entity: &Entity, group: &str) -> bool {
-        self.inner.borrow().entity_in_group(entity, group)
-    }
-}
-
-#[derive(Default)]
-pub struct GroupManagerInner {
-    entity_groups: HashMap<EntityId, HashSet<String>>,
-    group_entities: HashMap<String, HashSet<EntityId>>,
-}
-
-impl GroupManagerInner {
-    pub fn add_entity_to_group(&mut self, entity: &Entity, group: &str) {
-        self.entity_groups
-            .entry(entity.id())
+    pub(crate) fn add_entity_to_group(&mut self, entity: &Entity, group: &str) {
+        if !self
+            .entity_groups
+            .entry(*entity)
             .or_default()
-            .insert(group.to_string());
+            .insert(group.to_string())
+        {
+            return;
+        }
         self.group_entities
             .entry(group.to_string())
             .or_default()
-            .insert(entity.id());
+            .push(*entity);
+        self.changed.insert(*entity);
     }
 
-    pub fn remove_entity_from_group(&mut self, entity: &Entity, group: &str) {
-        if let Some(groups) = self.entity_groups.get_mut(&entity.id()) {
-            groups.remove(group);
+    pub(crate) fn remove_entity_from_group(&mut self, entity: &Entity, group: &str) {
+        let Some(groups) = self.entity_groups.get_mut(entity) else {
+            return;
+        };
+        if !groups.remove(group) {
+            return;
+        }
+        if groups.is_empty() {
+            self.entity_groups.remove(entity);
         }
 
         if let Some(entities) = self.group_entities.get_mut(group) {
-            entities.remove(&entity.id());
+            entities.retain(|e| e != entity);
+            if entities.is_empty() {
+                self.group_entities.remove(group);
+            }
         }
+        self.changed.insert(*entity);
     }
 
-    pub fn remove_entity(&mut self, entity: &Entity) {
-        if let Some(groups) = self.entity_groups.remove(&entity.id()) {
-            for group in groups {
-                if let Some(entities) = self.group_entities.get_mut(&group) {
-                    entities.remove(&entity.id());
-                }
-            }
+    pub(crate) fn remove_entity(&mut self, entity: &Entity) {
+        for group in self.groups_of(entity) {
+            self.remove_entity_from_group(entity, &group);
         }
     }
 
     pub fn group_contains_entity(&self, group: &str, entity: &Entity) -> bool {
-        if let Some(entities) = self.group_entities.get(group) {
-            entities.contains(&entity.id())
-        } else {
-            false
-        }
+        self.entity_in_group(entity, group)
     }
 
     pub fn entity_in_group(&self, entity: &Entity, group: &str) -> bool {
-        if let Some(groups) = self.entity_groups.get(&entity.id()) {
+        if let Some(groups) = self.entity_groups.get(entity) {
             groups.contains(group)
         } else {
             false
         }
     }
+
+    /// The groups of the entity, sorted by name.
+    pub fn groups_of(&self, entity: &Entity) -> Vec<String> {
+        let mut groups: Vec<String> = self
+            .entity_groups
+            .get(entity)
+            .map(|groups| groups.iter().cloned().collect())
+            .unwrap_or_default();
+        groups.sort();
+        groups
+    }
+
+    /// The entities in `group`, in the order they were added.
+    pub fn entities_in_group(&self, group: &str) -> Vec<Entity> {
+        self.group_entities.get(group).cloned().unwrap_or_default()
+    }
+
+    /// The number of entities in `group`.
+    pub fn group_count(&self, group: &str) -> usize {
+        self.group_entities.get(group).map_or(0, Vec::len)
+    }
+
+    /// The groups with at least one entity, sorted by name.
+    pub fn groups(&self) -> Vec<String> {
+        let mut groups: Vec<String> = self.group_entities.keys().cloned().collect();
+        groups.sort();
+        groups
+    }
+
+    /// Takes the entities whose groups changed since the last call.
+    pub(crate) fn take_changed(&mut self) -> HashSet<Entity> {
+        std::mem::take(&mut self.changed)
+    }
 }
@@ -0,0 +1,133 @@
+use super::{component::impl_component, em::EntityManagerInner, Entity};
+
+/// The parent of an entity. Added and removed by `EntityManager::set_parent` and
+/// `EntityManager::remove_parent`, which keep it in sync with the `Children` of the parent.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Parent(Entity);
+
+impl Parent {
+    pub fn get(&self) -> Entity {
+        self.0
+    }
+}
+
+/// The children of an entity, in the order they were added. Only present on entities with at
+/// least one child.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Children(Vec<Entity>);
+
+impl Children {
+    pub fn iter(&self) -> impl Iterator<Item = &Entity> {
+        self.0.iter()
+    }
+
+    pub fn as_slice(&self) -> &[Entity] {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl_component!(Parent, Children);
+
+impl EntityManagerInner {
+    /// Makes `parent` the parent of `child`, detaching `child` from its previous parent. Returns
+    /// `false` if either entity is not alive, or if `child` is `parent` or one of its ancestors.
+    pub fn set_parent(&mut self, child: Entity, parent: Entity) -> bool {
+        if !self.is_alive(child) || !self.is_alive(parent) {
+            return false;
+        }
+        if child == parent || self.ancestors(parent).contains(&child) {
+            return false;
+        }
+
+        self.remove_parent(child);
+        self.add_component(child, Parent(parent));
+        match self.get_component::<Children>(&parent) {
+            Some(children) => children.borrow_mut().0.push(child),
+            None => {
+                self.add_component(parent, Children(vec![child]));
+            }
+        }
+        true
+    }
+
+    /// Detaches the entity from its parent, making it a root. Returns the previous parent.
+    pub fn remove_parent(&mut self, child: Entity) -> Option<Entity> {
+        let parent = self.parent(child)?;
+        self.remove_component::<Parent>(child);
+
+        let children = self.get_component::<Children>(&parent)?;
+        let is_empty = {
+            let mut children = children.borrow_mut();
+            children.0.retain(|c| *c != child);
+            children.0.is_empty()
+        };
+        if is_empty {
+            self.remove_component::<Children>(parent);
+        }
+        Some(parent)
+    }
+
+    pub fn parent(&self, entity: Entity) -> Option<Entity> {
+        self.get_component::<Parent>(&entity)
+            .map(|parent| parent.borrow().0)
+    }
+
+    pub fn children(&self, entity: Entity) -> Vec<Entity> {
+        self.get_component::<Children>(&entity)
+            .map(|children| children.borrow().0.clone())
+            .unwrap_or_default()
+    }
+
+    /// The ancestors of the entity, from its parent up to the root.
+    pub fn ancestors(&self, entity: Entity) -> Vec<Entity> {
+        let mut ancestors = Vec::new();
+        let mut current = entity;
+        while let Some(parent) = self.parent(current) {
+            ancestors.push(parent);
+            current = parent;
+        }
+        ancestors
+    }
+
+    /// The descendants of the entity, depth first.
+    pub fn descendants(&self, entity: Entity) -> Vec<Entity> {
+        let mut descendants = Vec::new();
+        let mut stack = self.children(entity);
+        stack.reverse();
+        while let Some(child) = stack.pop() {
+            descendants.push(child);
+            let mut children = self.children(child);
+            children.reverse();
+            stack.extend(children);
+        }
+        descendants
+    }
+
+    /// Enqueues the entity and all of its descendants to be destroyed in the next update.
+    pub fn destroy_recursive(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+        for descendant in self.descendants(entity) {
+            self.destroy_entity(descendant);
+        }
+        self.destroy_entity(entity)
+    }
+
+    /// Detaches a despawning entity from its parent, and its children from it. Children that are
+    /// not despawned along with the entity become roots.
+    pub(crate) fn detach_despawned(&mut self, entity: Entity) {
+        self.remove_parent(entity);
+        for child in self.children(entity) {
+            self.remove_component::<Parent>(child);
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use std::{any::Any, cell::Ref, collections::BTreeMap, rc::Rc};
+
+use crate::Resources;
+
+use super::{
+    archetype::Archetype, em::EntityManagerInner, Commands, Component, ComponentRef,
+    ComponentTypeId, Entity, GroupManager, TagManager,
+};
+
+/// A hook run on a component of an entity, with the entity, the component and a view of the
+/// world.
+pub(crate) type Hook<C> = Rc<dyn Fn(Entity, &mut C, &DeferredWorld)>;
+
+/// The view of the world given to hooks. Hooks run while an entity is being changed, so they can
+/// read the entities, their components and the resources, but record their own changes with
+/// `commands`, applied at the next sync point.
+///
+/// ```ignore
+/// em.on_add::<ColliderComponent>(|entity, collider, world| {
+///     let transform = world.get_component::<Transform>(&entity).unwrap();
+///     world
+///         .resources()
+///         .get_mut::<SpatialIndex>()
+///         .unwrap()
+///         .insert(entity, transform.borrow().translation, collider.size);
+/// });
+/// ```
+pub struct DeferredWorld<'a> {
+    inner: &'a EntityManagerInner,
+}
+
+impl<'a> DeferredWorld<'a> {
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.inner.is_alive(entity)
+    }
+
+    /// Retrieves the component of type `C` from the entity, if available. The component the hook
+    /// runs on is borrowed while it runs, so borrowing it through a handle panics.
+    pub fn get_component<C: Component + 'static>(
+        &self,
+        entity: &Entity,
+    ) -> Option<ComponentRef<C>> {
+        self.inner.get_component::<C>(entity)
+    }
+
+    /// The parent of the entity, if any.
+    pub fn parent(&self, entity: Entity) -> Option<Entity> {
+        self.inner.parent(entity)
+    }
+
+    /// The children of the entity, in the order they were added.
+    pub fn children(&self, entity: Entity) -> Vec<Entity> {
+        self.inner.children(entity)
+    }
+
+    pub fn tag_manager(&self) -> &'a TagManager {
+        &self.inner.tag_manager
+    }
+
+    pub fn group_manager(&self) -> &'a GroupManager {
+        &self.inner.group_manager
+    }
+
+    /// The resources shared with the systems. Panics if they are borrowed mutably, for example
+    /// by a system changing entities while holding `resources.borrow_mut()`.
+    pub fn resources(&self) -> Ref<'a, Resources> {
+        self.inner.resources.borrow()
+    }
+
+    /// The buffer to record changes to the world, applied at the next sync point.
+    pub fn commands(&self) -> &'a Commands {
+        &self.inner.commands
+    }
+}
+
+/// When a hook runs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum HookKind {
+    /// The component was added to an entity that didn't have it.
+    Add,
+    /// The component was written to an entity, either added or replaced.
+    Insert,
+    /// The component is about to be removed, or the entity is about to be despawned.
+    Remove,
+}
+
+struct ComponentHooks<C> {
+    on_add: Vec<Hook<C>>,
+    on_insert: Vec<Hook<C>>,
+    on_remove: Vec<Hook<C>>,
+}
+
+/// Type erased operations over the `ComponentHooks` of a component type.
+trait AnyComponentHooks {
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    /// Runs the hooks of `kind` on the component of the entity at `row`.
+    fn run(
+        &self,
+        kind: HookKind,
+        archetype: &Archetype,
+        row: usize,
+        entity: Entity,
+        world: &DeferredWorld,
+    );
+}
+
+impl<C: Component + 'static> AnyComponentHooks for ComponentHooks<C> {
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn run(
+        &self,
+        kind: HookKind,
+        archetype: &Archetype,
+        row: usize,
+        entity: Entity,
+        world: &DeferredWorld,
+    ) {
+        let hooks = match kind {
+            HookKind::Add => &self.on_add,
+            HookKind::Insert => &self.on_insert,
+            HookKind::Remove => &self.on_remove,
+        };
+        if hooks.is_empty() {
+            return;
+        }
+
+        let Some(column) = archetype.column::<C>(C::get_type_id()) else {
+            return;
+        };
+        let mut value = column.cells[row].value.borrow_mut();
+        for hook in hooks {
+            hook(entity, &mut value, world);
+        }
+    }
+}
+
+/// The hooks registered for each component type, sorted by type id so the hooks of the
+/// components of a bundle always run in the same order.
+#[derive(Default)]
+pub(crate) struct HookRegistry {
+    hooks: BTreeMap<ComponentTypeId, Box<dyn AnyComponentHooks>>,
+}
+
+impl HookRegistry {
+    pub fn add<C: Component + 'static>(&mut self, kind: HookKind, hook: Hook<C>) {
+        let hooks = self
+            .hooks
+            .entry(C::get_type_id())
+            .or_insert_with(|| {
+                Box::new(ComponentHooks::<C> {
+                    on_add: Vec::new(),
+                    on_insert: Vec::new(),
+                    on_remove: Vec::new(),
+                })
+            })
+            .as_any_mut()
+            .downcast_mut::<ComponentHooks<C>>()
+            .unwrap();
+        match kind {
+            HookKind::Add => hooks.on_add.push(hook),
+            HookKind::Insert => hooks.on_insert.push(hook),
+            HookKind::Remove => hooks.on_remove.push(hook),
+        }
+    }
+
+    /// Runs the hooks of `kind` on the components of the entity at `row` whose type passes
+    /// `filter`, in type id order.
+    fn run(
+        &self,
+        kind: HookKind,
+        filter: impl Fn(ComponentTypeId) -> bool,
+        archetype: &Archetype,
+        row: usize,
+        entity: Entity,
+        world: &DeferredWorld,
+    ) {
+        for (type_id, hooks) in &self.hooks {
+            if filter(*type_id) {
+                hooks.run(kind, archetype, row, entity, world);
+            }
+        }
+    }
+}
+
+impl EntityManagerInner {
+    pub fn add_hook<C: Component + 'static>(&mut self, kind: HookKind, hook: Hook<C>) {
+        self.hooks.add(kind, hook);
+    }
+
+    /// Runs the hooks of `kind` on the components of the entity whose type passes `filter`.
+    pub(crate) fn run_hooks(
+        &self,
+        kind: HookKind,
+        entity: Entity,
+        filter: impl Fn(ComponentTypeId) -> bool,
+    ) {
+        let Some(location) = self.archetypes.location(entity.id()) else {
+            return;
+        };
+        let archetype = self.archetypes.get(location.archetype);
+        self.hooks.run(
+            kind,
+            filter,
+            archetype,
+            location.row,
+            entity,
+            &DeferredWorld { inner: self },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, time::Duration};
+
+    use super::*;
+    use crate::{entity_manager::impl_component, EntityComponentSystem};
+
+    struct Position(u32);
+    struct Collider(u32);
+    struct Marker;
+    impl_component!(Position, Collider, Marker);
+
+    #[derive(Default)]
+    struct SpatialIndex(Vec<(Entity, u32)>);
+
+    #[test]
+    fn hooks_read_other_components_and_resources() {
+        let ecs = EntityComponentSystem::new();
+        ecs.resources_mut().init_resource::<SpatialIndex>();
+        let em = ecs.entity_manager();
+        em.on_add::<Collider>(|entity, collider, world| {
+            let position = world.get_component::<Position>(&entity).unwrap();
+            let resources = world.resources();
+            let mut index = resources.get_mut::<SpatialIndex>().unwrap();
+            index.0.push((entity, position.borrow().0 + collider.0));
+            world.commands().add_component(entity, Marker);
+        });
+
+        let entity = em.create_entity();
+        em.add_bundle(entity, (Position(1), Collider(2)));
+        ecs.update(Duration::ZERO);
+
+        assert_eq!(
+            ecs.resources().get::<SpatialIndex>().unwrap().0,
+            vec![(entity, 3)]
+        );
+        assert!(em.get_component::<Marker>(&entity).is_some());
+    }
+
+    #[test]
+    fn hooks_of_a_bundle_run_in_type_id_order() {
+        let em = crate::EntityManager::new();
+        let order = Rc::new(RefCell::new(Vec::new()));
+        let log = order.clone();
+        em.on_insert::<Collider>(move |_, _, _| log.borrow_mut().push(Collider::get_type_id()));
+        let log = order.clone();
+        em.on_insert::<Position>(move |_, _, _| log.borrow_mut().push(Position::get_type_id()));
+
+        let entity = em.create_entity();
+        em.add_bundle(entity, (Collider(0), Position(0)));
+
+        let mut sorted = order.borrow().clone();
+        sorted.sort();
+        assert_eq!(order.borrow().len(), 2);
+        assert_eq!(*order.borrow(), sorted);
+    }
+}
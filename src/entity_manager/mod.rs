@@ -1,13 +1,36 @@
+mod archetype;
+mod bundle;
+mod change_detection;
+mod clone;
+mod commands;
 mod component;
 mod em;
 mod entity;
+mod entity_builder;
 mod group_manager;
+mod hierarchy;
+mod hooks;
+mod prefab;
+mod query;
+mod relation;
+mod removed;
 mod tag_manager;
-// mod query;
 
-pub use component::{get_next_component_type_id, Component, ComponentTypeId};
-pub use entity::{Entity, EntityId};
-pub use tag_manager::TagManager;
-// pub use query::Query;
+pub use archetype::Archetype;
+pub use bundle::Bundle;
+pub use change_detection::{ComponentTicks, Mut, Tick};
+pub use commands::Commands;
+pub(crate) use component::impl_component;
+pub use component::{get_next_component_type_id, Component, ComponentRef, ComponentTypeId};
 pub use em::EntityManager;
+pub(crate) use em::EntityManagerInner;
+pub use entity::{Entity, EntityId, Generation};
+pub use entity_builder::{EntityBuilder, SpawnError};
 pub use group_manager::GroupManager;
+pub use hierarchy::{Children, Parent};
+pub use hooks::DeferredWorld;
+pub use prefab::Prefab;
+pub use query::{Added, AnyOf, Changed, Query, QueryFilter, QueryParam, With, Without};
+pub use relation::{Relation, RelationCleanup};
+pub use removed::RemovedComponents;
+pub use tag_manager::TagManager;
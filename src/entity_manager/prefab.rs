@@ -0,0 +1,267 @@
+use std::rc::Rc;
+
+use crate::component_signature::ComponentSignature;
+
+use super::{
+    archetype::Archetype,
+    em::EntityManagerInner,
+    entity_builder::{EntityTags, Insert},
+    Bundle, Entity, Tick,
+};
+
+/// Writes a copy of a component of a prefab to a new instance, at the row of the instance.
+type PrefabInsert = Rc<dyn Fn(&mut Archetype, usize, Tick)>;
+
+/// A named template for entities, registered with `EntityManager::register_prefab`: a set of
+/// component values, a tag, labels, groups, and the names of the prefabs to instantiate as children.
+///
+/// ```ignore
+/// em.register_prefab(
+///     "bullet",
+///     Prefab::new()
+///         .with(SpriteComponent::new("bullet", Vec2::new(4.0, 4.0)))
+///         .with(VelocityComponent(Vec2::new(150.0, 0.0)))
+///         .group("projectile"),
+/// );
+///
+/// let bullet = em
+///     .instantiate("bullet")
+///     .unwrap()
+///     .with(Transform::from_translation(position))
+///     .id();
+/// ```
+///
+/// Every instance gets its own copy of the components, which must therefore be `Clone`.
+#[derive(Clone, Default)]
+pub struct Prefab {
+    pub(crate) signature: ComponentSignature,
+    inserts: Vec<PrefabInsert>,
+    pub(crate) tags: EntityTags,
+    pub(crate) children: Vec<String>,
+}
+
+impl Prefab {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a component, or all the components of a bundle. Adding a component type twice keeps
+    /// the last one.
+    pub fn with<B: Bundle + Clone>(mut self, bundle: B) -> Self {
+        B::add_to_signature(&mut self.signature);
+        self.inserts.push(Rc::new(move |archetype, row, tick| {
+            bundle.clone().insert_into(archetype, row, tick)
+        }));
+        self
+    }
+
+    /// Tags the instances with `tag`. As tags are unique, each new instance takes the tag from the
+    /// entity that had it, whether it is instantiated through the `EntityManager` or `Commands`.
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.tag = Some(tag.to_string());
+        self
+    }
+
+    /// Adds `label` to the labels of the instances.
+    pub fn label(mut self, label: &str) -> Self {
+        self.tags.labels.push(label.to_string());
+        self
+    }
+
+    /// Adds the instances to `group`.
+    pub fn group(mut self, group: &str) -> Self {
+        self.tags.groups.push(group.to_string());
+        self
+    }
+
+    /// Instantiates the prefab named `prefab` as a child of each instance. The child prefab is
+    /// looked up when instantiating, so it can be registered after this one. Children whose
+    /// prefab isn't registered, or that are one of their own ancestors, are skipped.
+    pub fn child(mut self, prefab: &str) -> Self {
+        self.children.push(prefab.to_string());
+        self
+    }
+
+    /// The writes of the components of a new instance.
+    pub(crate) fn inserts(&self) -> Vec<Insert> {
+        self.inserts
+            .iter()
+            .map(|insert| {
+                let insert = insert.clone();
+                Box::new(move |archetype: &mut Archetype, row, tick| insert(archetype, row, tick))
+                    as Insert
+            })
+            .collect()
+    }
+}
+
+impl EntityManagerInner {
+    /// Registers `prefab` under `name`, replacing the prefab previously registered with that name.
+    pub fn register_prefab(&mut self, name: &str, prefab: Prefab) {
+        self.prefabs.insert(name.to_string(), Rc::new(prefab));
+    }
+
+    pub(crate) fn prefab(&self, name: &str) -> Option<Rc<Prefab>> {
+        self.prefabs.get(name).cloned()
+    }
+
+    /// Spawns an instance of the prefab named `name` as the entity reserved by `Commands`, along
+    /// with its children. Returns `false` if no prefab is registered with that name.
+    pub(crate) fn instantiate_reserved(&mut self, entity: Entity, name: &str) -> bool {
+        let Some(prefab) = self.prefab(name) else {
+            // Release the reserved slot, as the entity will never be spawned.
+            let mut allocator = self.allocator.borrow_mut();
+            allocator.activate(entity);
+            allocator.free(entity);
+            return false;
+        };
+        self.spawn_reserved(entity);
+        self.fill_from_prefab(entity, &prefab, &mut vec![name.to_string()]);
+        true
+    }
+
+    /// Instantiates the prefabs named `children` as children of `parent`. Children are spawned the
+    /// same way as their parent: right away if the parent was spawned by `Commands`, or in the
+    /// next update otherwise.
+    ///
+    /// `chain` holds the names of the prefabs being instantiated, from the root down to the prefab
+    /// of `parent`. Children already in the chain are skipped, as instantiating them would never
+    /// end.
+    pub(crate) fn instantiate_children(
+        &mut self,
+        parent: Entity,
+        children: &[String],
+        chain: &mut Vec<String>,
+    ) {
+        for name in children {
+            if chain.contains(name) {
+                tracing::warn!(
+                    "skipped child prefab {name:?}, which is one of its own ancestors: {}",
+                    chain.join(" -> ")
+                );
+                continue;
+            }
+            let Some(prefab) = self.prefab(name) else {
+                continue;
+            };
+
+            let child = if self.entities_to_spawn.contains(&parent) {
+                self.create_entity()
+            } else {
+                let child = self.allocator.borrow_mut().reserve();
+                self.spawn_reserved(child);
+                child
+            };
+            chain.push(name.clone());
+            self.fill_from_prefab(child, &prefab, chain);
+            chain.pop();
+            self.set_parent(child, parent);
+        }
+    }
+
+    // Writes the components, tag, labels and groups of the prefab to an entity without components, and
+    // instantiates the children of the prefab. Runs before the entity gets its parent, which
+    // writing the components would remove.
+    fn fill_from_prefab(&mut self, entity: Entity, prefab: &Prefab, chain: &mut Vec<String>) {
+        self.insert_with(entity, &prefab.signature, prefab.inserts(), &prefab.tags);
+        self.instantiate_children(entity, &prefab.children, chain);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::entity_manager::{impl_component, EntityManager, Parent};
+    use crate::EntityComponentSystem;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Health(u32);
+    #[derive(Clone)]
+    struct Sprite;
+    impl_component!(Health, Sprite);
+
+    #[test]
+    fn tagged_prefab_instances_take_the_tag_through_both_paths() {
+        let ecs = EntityComponentSystem::new();
+        let em = ecs.entity_manager();
+        em.register_prefab("player", Prefab::new().tag("player"));
+
+        let first = em.instantiate("player").unwrap().id();
+        let second = em.instantiate("player").unwrap().id();
+        assert_eq!(em.tag_manager().get_entity("player"), Some(second));
+        assert_eq!(em.tag_manager().get_tag(first), None);
+
+        let third = em.commands().instantiate("player");
+        ecs.update(Duration::ZERO);
+        assert_eq!(em.tag_manager().get_entity("player"), Some(third));
+        assert_eq!(em.tag_manager().get_tag(second), None);
+    }
+
+    #[test]
+    fn prefabs_that_are_their_own_descendants_are_skipped() {
+        let ecs = EntityComponentSystem::new();
+        let em = ecs.entity_manager();
+        em.register_prefab("a", Prefab::new().child("b"));
+        em.register_prefab("b", Prefab::new().child("a").child("c"));
+        em.register_prefab("c", Prefab::new().child("c"));
+
+        let a = em.instantiate("a").unwrap().id();
+        let b = em.children(a);
+        assert_eq!(b.len(), 1);
+        let c = em.children(b[0]);
+        assert_eq!(c.len(), 1);
+        assert!(em.children(c[0]).is_empty());
+
+        let a = em.commands().instantiate("a");
+        ecs.update(Duration::ZERO);
+        assert_eq!(em.descendants(a).len(), 2);
+    }
+
+    #[test]
+    fn components_added_to_an_instance_replace_those_of_the_prefab() {
+        let em = EntityManager::new();
+        em.register_prefab("enemy", Prefab::new().with((Health(10), Sprite)));
+
+        let instance = em.instantiate("enemy").unwrap().with(Health(3)).id();
+        em.update();
+        assert_eq!(
+            *em.get_component::<Health>(&instance).unwrap().borrow(),
+            Health(3)
+        );
+        assert!(em.get_component::<Sprite>(&instance).is_some());
+
+        let prefab = em.instantiate("enemy").unwrap().id();
+        em.update();
+        assert_eq!(
+            *em.get_component::<Health>(&prefab).unwrap().borrow(),
+            Health(10)
+        );
+    }
+
+    #[test]
+    fn child_prefabs_are_parented_to_the_instance() {
+        let ecs = EntityComponentSystem::new();
+        let em = ecs.entity_manager();
+        em.register_prefab("ship", Prefab::new().child("turret").child("turret"));
+        em.register_prefab("turret", Prefab::new().with(Sprite));
+
+        let ship = em.instantiate("ship").unwrap().id();
+        let commanded = em.commands().instantiate("ship");
+        ecs.update(Duration::ZERO);
+
+        for instance in [ship, commanded] {
+            let turrets = em.children(instance);
+            assert_eq!(turrets.len(), 2);
+            for turret in turrets {
+                assert_eq!(em.parent(turret), Some(instance));
+                assert_eq!(
+                    em.get_component::<Parent>(&turret).unwrap().borrow().get(),
+                    instance
+                );
+                assert!(em.get_component::<Sprite>(&turret).is_some());
+            }
+        }
+    }
+}
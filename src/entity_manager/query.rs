@@ -1,157 +1,556 @@
 use std::{
-    any::Any,
-    cell::{Ref, RefCell, RefMut},
-    collections::HashMap,
+    any::type_name,
+    cell::{Ref, RefMut},
     marker::PhantomData,
+    rc::Rc,
 };
 
 use crate::component_signature::ComponentSignature;
 
-use super::{component::Component, EntityId, EntityManager};
-
-pub struct Query<'a, T> {
-    pub(crate) phantom: PhantomData<T>,
-    pub(crate) em: &'a EntityManager,
-}
-
-impl<'a, T> Query<'a, T> {
-    pub fn new(em: &'a EntityManager) -> Self {
-        Self { phantom: PhantomData, em }
-    }
-}
-
-impl<'a, A: Component + 'static> Query<'a, Ref<'a, A>> {
-    pub fn values(&'a self) -> Vec<Ref<'a, A>> {
-        // Get the component ID.
-        let component_id = &A::get_type_id();
-
-        // Get the components from the list. Return an empty vector if the list is empty.
-        let Some(components) = self.em.inner.borrow().components.get(component_id) else {
-            return vec![];
-        };
-
-        // Get the components values, borrow the RefCell and cast to type A.
-        components
-            .values()
-            .map(|c| Ref::map(c.borrow(), |f| f.downcast_ref::<A>().unwrap()))
-            .collect::<Vec<_>>()
+use super::{
+    archetype::{Archetype, ComponentCell},
+    component::Component,
+    ComponentTypeId, Entity, EntityManager, Mut, Tick,
+};
+
+type Cells<'a, C> = &'a [Rc<ComponentCell<C>>];
+
+// The cells of a column, along with the borrow of the component last fetched from it. Components
+// are borrowed one at a time, so a query doesn't conflict with `ComponentRef` handles to the
+// components of the entities it skips.
+type BorrowedCells<'a, C> = (Cells<'a, C>, Option<Ref<'a, C>>);
+type MutBorrowedCells<'a, C> = (Cells<'a, C>, Option<RefMut<'a, C>>, Tick);
+
+fn cells<C: Component + 'static>(archetype: &Archetype) -> Option<Cells<'_, C>> {
+    archetype
+        .column::<C>(C::get_type_id())
+        .map(|column| column.cells.as_slice())
+}
+
+fn fetch_ref<'c, C>(column: &'c mut BorrowedCells<'_, C>, row: usize) -> &'c C {
+    let (cells, borrow) = column;
+    borrow.insert(cells[row].value.borrow())
+}
+
+fn fetch_mut<'c, C>(column: &'c mut MutBorrowedCells<'_, C>, row: usize) -> Mut<'c, C> {
+    let (cells, borrow, change_tick) = column;
+    let cell = &cells[row];
+    Mut::new(
+        &mut **borrow.insert(cell.value.borrow_mut()),
+        &cell.ticks,
+        *change_tick,
+    )
+}
+
+/// A component access that can be part of a `Query`. Implemented for `&C`, `&mut C`, `Option<&C>`,
+/// `Option<&mut C>`, and tuples of up to eight of those. Mutable accesses yield a `Mut<C>`, which
+/// marks the component as changed when it is mutated.
+pub trait QueryParam {
+    /// The borrowed columns of an archetype.
+    #[doc(hidden)]
+    type Column<'a>;
+
+    /// The data yielded for each entity.
+    type Item<'c>;
+
+    /// Adds the components required by the parameter to `signature`.
+    fn add_to_signature(signature: &mut ComponentSignature);
+
+    /// Adds the components borrowed by the parameter to `accesses`, along with whether they are
+    /// borrowed mutably.
+    #[doc(hidden)]
+    fn add_accesses(accesses: &mut Vec<(ComponentTypeId, bool)>);
+
+    #[doc(hidden)]
+    fn borrow_column(archetype: &Archetype, change_tick: Tick) -> Self::Column<'_>;
+
+    #[doc(hidden)]
+    fn fetch<'c>(column: &'c mut Self::Column<'_>, row: usize) -> Self::Item<'c>;
+}
+
+impl<C: Component + 'static> QueryParam for &C {
+    type Column<'a> = BorrowedCells<'a, C>;
+    type Item<'c> = &'c C;
+
+    fn add_to_signature(signature: &mut ComponentSignature) {
+        signature.require_component::<C>();
+    }
+
+    fn add_accesses(accesses: &mut Vec<(ComponentTypeId, bool)>) {
+        accesses.push((C::get_type_id(), false));
+    }
+
+    fn borrow_column(archetype: &Archetype, _change_tick: Tick) -> Self::Column<'_> {
+        (cells::<C>(archetype).unwrap(), None)
+    }
+
+    fn fetch<'c>(column: &'c mut Self::Column<'_>, row: usize) -> Self::Item<'c> {
+        fetch_ref(column, row)
+    }
+}
+
+impl<C: Component + 'static> QueryParam for &mut C {
+    type Column<'a> = MutBorrowedCells<'a, C>;
+    type Item<'c> = Mut<'c, C>;
+
+    fn add_to_signature(signature: &mut ComponentSignature) {
+        signature.require_component::<C>();
+    }
+
+    fn add_accesses(accesses: &mut Vec<(ComponentTypeId, bool)>) {
+        accesses.push((C::get_type_id(), true));
+    }
+
+    fn borrow_column(archetype: &Archetype, change_tick: Tick) -> Self::Column<'_> {
+        (cells::<C>(archetype).unwrap(), None, change_tick)
+    }
+
+    fn fetch<'c>(column: &'c mut Self::Column<'_>, row: usize) -> Self::Item<'c> {
+        fetch_mut(column, row)
+    }
+}
+
+impl<C: Component + 'static> QueryParam for Option<&C> {
+    type Column<'a> = Option<BorrowedCells<'a, C>>;
+    type Item<'c> = Option<&'c C>;
+
+    fn add_to_signature(_signature: &mut ComponentSignature) {}
+
+    fn add_accesses(accesses: &mut Vec<(ComponentTypeId, bool)>) {
+        accesses.push((C::get_type_id(), false));
+    }
+
+    fn borrow_column(archetype: &Archetype, _change_tick: Tick) -> Self::Column<'_> {
+        cells::<C>(archetype).map(|cells| (cells, None))
+    }
+
+    fn fetch<'c>(column: &'c mut Self::Column<'_>, row: usize) -> Self::Item<'c> {
+        column.as_mut().map(|column| fetch_ref(column, row))
+    }
+}
+
+impl<C: Component + 'static> QueryParam for Option<&mut C> {
+    type Column<'a> = Option<MutBorrowedCells<'a, C>>;
+    type Item<'c> = Option<Mut<'c, C>>;
+
+    fn add_to_signature(_signature: &mut ComponentSignature) {}
+
+    fn add_accesses(accesses: &mut Vec<(ComponentTypeId, bool)>) {
+        accesses.push((C::get_type_id(), true));
+    }
+
+    fn borrow_column(archetype: &Archetype, change_tick: Tick) -> Self::Column<'_> {
+        cells::<C>(archetype).map(|cells| (cells, None, change_tick))
+    }
+
+    fn fetch<'c>(column: &'c mut Self::Column<'_>, row: usize) -> Self::Item<'c> {
+        column.as_mut().map(|column| fetch_mut(column, row))
+    }
+}
+
+macro_rules! impl_query_param_tuple {
+    ($($param:ident),*) => {
+        impl<$($param: QueryParam),*> QueryParam for ($($param,)*) {
+            type Column<'a> = ($($param::Column<'a>,)*);
+            type Item<'c> = ($($param::Item<'c>,)*);
+
+            fn add_to_signature(signature: &mut ComponentSignature) {
+                $($param::add_to_signature(signature);)*
+            }
+
+            fn add_accesses(accesses: &mut Vec<(ComponentTypeId, bool)>) {
+                $($param::add_accesses(accesses);)*
+            }
+
+            fn borrow_column(archetype: &Archetype, change_tick: Tick) -> Self::Column<'_> {
+                ($($param::borrow_column(archetype, change_tick),)*)
+            }
+
+            #[allow(non_snake_case)]
+            fn fetch<'c>(column: &'c mut Self::Column<'_>, row: usize) -> Self::Item<'c> {
+                let ($($param,)*) = column;
+                ($($param::fetch($param, row),)*)
+            }
+        }
+    };
+}
+
+impl_query_param_tuple!(A);
+impl_query_param_tuple!(A, B);
+impl_query_param_tuple!(A, B, C);
+impl_query_param_tuple!(A, B, C, D);
+impl_query_param_tuple!(A, B, C, D, E);
+impl_query_param_tuple!(A, B, C, D, E, F);
+impl_query_param_tuple!(A, B, C, D, E, F, G);
+impl_query_param_tuple!(A, B, C, D, E, F, G, H);
+
+/// A filter restricting the entities matched by a `Query`, without yielding their components.
+/// Implemented for `With<C>`, `Without<C>`, `AnyOf<(A, B, ...)>`, `Added<C>`, `Changed<C>`, and
+/// tuples of up to eight filters.
+pub trait QueryFilter {
+    /// The borrowed columns of an archetype.
+    #[doc(hidden)]
+    type Column<'a>;
+
+    /// Adds the components required or excluded by the filter to `signature`.
+    fn add_to_signature(signature: &mut ComponentSignature);
+
+    #[doc(hidden)]
+    fn borrow_column(archetype: &Archetype) -> Self::Column<'_>;
+
+    /// Returns `true` if the entity at `row` passes the filter.
+    #[doc(hidden)]
+    fn filter(column: &Self::Column<'_>, row: usize, last_run: Tick) -> bool;
+}
+
+/// Matches entities that have the component `C`.
+pub struct With<C>(PhantomData<C>);
+
+/// Matches entities that don't have the component `C`.
+pub struct Without<C>(PhantomData<C>);
+
+/// Matches entities that have at least one of the components in the tuple `T`.
+pub struct AnyOf<T>(PhantomData<T>);
+
+/// Matches entities whose component `C` was added since the current system last ran.
+pub struct Added<C>(PhantomData<C>);
+
+/// Matches entities whose component `C` was added or mutated since the current system last ran.
+pub struct Changed<C>(PhantomData<C>);
+
+impl QueryFilter for () {
+    type Column<'a> = ();
+
+    fn add_to_signature(_signature: &mut ComponentSignature) {}
+
+    fn borrow_column(_archetype: &Archetype) -> Self::Column<'_> {}
+
+    fn filter(_column: &Self::Column<'_>, _row: usize, _last_run: Tick) -> bool {
+        true
+    }
+}
+
+impl<C: Component> QueryFilter for With<C> {
+    type Column<'a> = ();
+
+    fn add_to_signature(signature: &mut ComponentSignature) {
+        signature.require_component::<C>();
+    }
+
+    fn borrow_column(_archetype: &Archetype) -> Self::Column<'_> {}
+
+    fn filter(_column: &Self::Column<'_>, _row: usize, _last_run: Tick) -> bool {
+        true
+    }
+}
+
+impl<C: Component> QueryFilter for Without<C> {
+    type Column<'a> = ();
+
+    fn add_to_signature(signature: &mut ComponentSignature) {
+        signature.exclude_component::<C>();
+    }
+
+    fn borrow_column(_archetype: &Archetype) -> Self::Column<'_> {}
+
+    fn filter(_column: &Self::Column<'_>, _row: usize, _last_run: Tick) -> bool {
+        true
     }
 }
 
-impl<'a, A: Component + 'static> From<&'a EntityManager> for Query<'a, Ref<'a, A>> {
-    fn from(em: &'a EntityManager) -> Self {
-        Self::new(em)
+impl<C: Component + 'static> QueryFilter for Added<C> {
+    type Column<'a> = Cells<'a, C>;
+
+    fn add_to_signature(signature: &mut ComponentSignature) {
+        signature.require_component::<C>();
+    }
+
+    fn borrow_column(archetype: &Archetype) -> Self::Column<'_> {
+        cells::<C>(archetype).unwrap()
+    }
+
+    fn filter(column: &Self::Column<'_>, row: usize, last_run: Tick) -> bool {
+        column[row].ticks.get().is_added(last_run)
     }
 }
 
-impl<'a, A: Component + 'static, B: Component + 'static> Query<'a, (RefMut<'a, A>, Ref<'a, B>)> {
-    pub fn values(&'a self) -> Vec<(RefMut<'a, A>, Ref<'a, B>)> {
-        // Create a signature for the query, using the IDs for the Component types.
-        let mut query_signature = ComponentSignature::default();
-        query_signature.require_component::<A>();
-        query_signature.require_component::<B>();
+impl<C: Component + 'static> QueryFilter for Changed<C> {
+    type Column<'a> = Cells<'a, C>;
+
+    fn add_to_signature(signature: &mut ComponentSignature) {
+        signature.require_component::<C>();
+    }
+
+    fn borrow_column(archetype: &Archetype) -> Self::Column<'_> {
+        cells::<C>(archetype).unwrap()
+    }
+
+    fn filter(column: &Self::Column<'_>, row: usize, last_run: Tick) -> bool {
+        column[row].ticks.get().is_changed(last_run)
+    }
+}
+
+macro_rules! impl_query_filter_tuple {
+    ($($param:ident),*) => {
+        impl<$($param: QueryFilter),*> QueryFilter for ($($param,)*) {
+            type Column<'a> = ($($param::Column<'a>,)*);
+
+            fn add_to_signature(signature: &mut ComponentSignature) {
+                $($param::add_to_signature(signature);)*
+            }
+
+            fn borrow_column(archetype: &Archetype) -> Self::Column<'_> {
+                ($($param::borrow_column(archetype),)*)
+            }
+
+            #[allow(non_snake_case)]
+            fn filter(column: &Self::Column<'_>, row: usize, last_run: Tick) -> bool {
+                let ($($param,)*) = column;
+                $($param::filter($param, row, last_run))&&*
+            }
+        }
+
+        impl<$($param: Component),*> QueryFilter for AnyOf<($($param,)*)> {
+            type Column<'a> = ();
+
+            fn add_to_signature(signature: &mut ComponentSignature) {
+                let mut group = ComponentSignature::default();
+                $(group.require_component::<$param>();)*
+                signature.require_any_of(&group);
+            }
+
+            fn borrow_column(_archetype: &Archetype) -> Self::Column<'_> {}
+
+            fn filter(_column: &Self::Column<'_>, _row: usize, _last_run: Tick) -> bool {
+                true
+            }
+        }
+    };
+}
+
+impl_query_filter_tuple!(A);
+impl_query_filter_tuple!(A, B);
+impl_query_filter_tuple!(A, B, C);
+impl_query_filter_tuple!(A, B, C, D);
+impl_query_filter_tuple!(A, B, C, D, E);
+impl_query_filter_tuple!(A, B, C, D, E, F);
+impl_query_filter_tuple!(A, B, C, D, E, F, G);
+impl_query_filter_tuple!(A, B, C, D, E, F, G, H);
+
+/// A typed view over all the entities that have the components in `T` and pass the filter `F`.
+/// For example, `Query<(&mut Transform, &VelocityComponent, Option<&SpriteComponent>)>`
+/// matches every entity with a transform and a velocity, and yields the sprite when the entity
+/// has one. Adding `Without<KeyboardControlComponent>` as the filter skips the entities controlled
+/// by the player, and `Changed<Transform>` skips the ones that haven't moved since the
+/// current system last ran.
+///
+/// Components are borrowed while the query runs, so adding or removing components, or creating and
+/// destroying entities, from inside the closures panics. Collect the entities and apply the
+/// changes after the query instead.
+///
+/// Like `EntityManager::get_entities_with_signature`, queries skip the entities created since the
+/// last update, until the update adds them to the systems.
+///
+/// A component borrowed mutably can't be borrowed a second time by the same query, so queries
+/// such as `Query<(&mut Transform, &Transform)>` panic when they are created.
+pub struct Query<T: QueryParam, F: QueryFilter = ()> {
+    em: EntityManager,
+    signature: ComponentSignature,
+    phantom: PhantomData<(T, F)>,
+}
+
+impl<T: QueryParam, F: QueryFilter> Query<T, F> {
+    /// # Panics
+    ///
+    /// Panics if `T` borrows a component mutably along with another borrow of the same component.
+    pub fn new(em: &EntityManager) -> Self {
+        let mut accesses = Vec::new();
+        T::add_accesses(&mut accesses);
+        for (i, (type_id, mutable)) in accesses.iter().enumerate() {
+            let conflicts = accesses[..i]
+                .iter()
+                .any(|(other, other_mutable)| other == type_id && (*mutable || *other_mutable));
+            assert!(
+                !conflicts,
+                "Query<{}> borrows a component mutably along with another borrow of the same \
+                 component",
+                type_name::<T>()
+            );
+        }
+
+        let mut signature = ComponentSignature::default();
+        T::add_to_signature(&mut signature);
+        F::add_to_signature(&mut signature);
+        Self { em: em.clone(), signature, phantom: PhantomData }
+    }
 
-        // Filter the entities by the query signature, then map to the component pairs.
-        let entities = self
-            .em
-            .entities
-            .values()
-            .filter(|e| {
-                let Some(entity_signature) = self.em.entity_component_signatures.get(&e.id())
-                else {
-                    return false;
-                };
-                query_signature.is_subset(entity_signature)
-            })
-            .map(|a| get_component::<A, B>(&self.em.components, a.id()))
-            .collect::<Vec<_>>();
-
-        entities
-    }
-}
-
-impl<'a, A: Component + 'static, B: Component + 'static> Query<'a, (Ref<'a, A>, Ref<'a, B>)> {
-    pub fn values(&'a self) -> Vec<(Ref<'a, A>, Ref<'a, B>)> {
-        // Get the IDs for the Component types.
-        let a_id = A::get_type_id();
-        let b_id = B::get_type_id();
-
-        // Create a signature for the query, using the IDs for the Component types.
-        let mut query_signature = ComponentSignature::default();
-        query_signature.require_component::<A>();
-        query_signature.require_component::<B>();
-
-        // Filter the entities by the query signature, then map to the component pairs.
-        let entities = self
-            .em
-            .entities
-            .values()
-            .filter(|e| {
-                let Some(entity_signature) = self.em.entity_component_signatures.get(&e.id())
-                else {
-                    return false;
-                };
-                query_signature.is_subset(entity_signature)
-            })
-            .map(|entity| {
-                let a = self
-                    .em
-                    .components
-                    .get(&a_id)
-                    .unwrap()
-                    .get(&entity.id())
-                    .unwrap();
-                let a = Ref::map(a.borrow(), |f| f.downcast_ref::<A>().unwrap());
-
-                // Get component A from the component storage, then typecast to the correct type.
-                let b = self
-                    .em
-                    .components
-                    .get(&b_id)
-                    .unwrap()
-                    .get(&entity.id())
-                    .unwrap();
-                let b = Ref::map(b.borrow(), |f| f.downcast_ref::<B>().unwrap());
-                (a, b)
-            })
-            .collect::<Vec<_>>();
-
-        entities
-    }
-}
-
-impl<'a, A: Component + 'static, B: Component + 'static> From<&'a EntityManager>
-    for Query<'a, (RefMut<'a, A>, Ref<'a, B>)>
-{
-    fn from(em: &'a EntityManager) -> Self {
-        Self::new(em)
-    }
-}
-
-impl<'a, A: Component + 'static, B: Component + 'static> From<&'a EntityManager>
-    for Query<'a, (Ref<'a, A>, Ref<'a, B>)>
-{
-    fn from(em: &'a EntityManager) -> Self {
-        Self::new(em)
-    }
-}
-
-type AnyRefCell = RefCell<Box<dyn Any>>;
-
-// Builds a component pair from the components HashMap.
-fn get_component<A: Component + 'static, B: Component + 'static>(
-    components: &HashMap<usize, HashMap<usize, AnyRefCell>>,
-    entity_id: EntityId,
-) -> (RefMut<A>, Ref<B>) {
-    let a_id = A::get_type_id();
-    let b_id = B::get_type_id();
-
-    // Get component A from the component storage, then typecast to the correct type.
-    let a = components.get(&a_id).unwrap().get(&entity_id).unwrap();
-    let a = RefMut::map(a.borrow_mut(), |f| f.downcast_mut::<A>().unwrap());
-
-    // Get component A from the component storage, then typecast to the correct type.
-    let b = components.get(&b_id).unwrap().get(&entity_id).unwrap();
-    let b = Ref::map(b.borrow(), |f| f.downcast_ref::<B>().unwrap());
-    (a, b)
+    /// Only matches the entities with `tag`, as their unique tag or as a label.
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.signature.require_tag(tag);
+        self
+    }
+
+    /// Skips the entities with `tag`, as their unique tag or as a label.
+    pub fn without_tag(mut self, tag: &str) -> Self {
+        self.signature.exclude_tag(tag);
+        self
+    }
+
+    /// Only matches the entities in `group`.
+    pub fn with_group(mut self, group: &str) -> Self {
+        self.signature.require_group(group);
+        self
+    }
+
+    /// Skips the entities in `group`.
+    pub fn without_group(mut self, group: &str) -> Self {
+        self.signature.exclude_group(group);
+        self
+    }
+
+    /// The signature an entity must match to be part of the query.
+    pub fn signature(&self) -> &ComponentSignature {
+        &self.signature
+    }
+
+    /// Calls `f` with each entity matching the query and its components.
+    pub fn for_each(&self, mut f: impl FnMut(Entity, T::Item<'_>)) {
+        let inner = self.em.inner.borrow();
+        let last_run = inner.last_run();
+        for archetype in inner.archetypes.iter() {
+            if !self.signature.matches(archetype.signature()) {
+                continue;
+            }
+
+            let entities = archetype.entities().borrow();
+            if entities.is_empty() {
+                continue;
+            }
+
+            let filter = F::borrow_column(archetype);
+            let mut columns = T::borrow_column(archetype, inner.change_tick());
+            for (row, entity) in entities.iter().enumerate() {
+                if F::filter(&filter, row, last_run)
+                    && inner.is_matched_by(&self.signature, *entity)
+                {
+                    f(*entity, T::fetch(&mut columns, row));
+                }
+            }
+        }
+    }
+
+    /// Calls `f` with the components of `entity`. Returns `None` if the entity is not alive, was
+    /// created since the last update, or doesn't match the query.
+    pub fn get<R>(&self, entity: Entity, f: impl FnOnce(T::Item<'_>) -> R) -> Option<R> {
+        let inner = self.em.inner.borrow();
+        if !inner.is_alive(entity) {
+            return None;
+        }
+
+        let location = inner.archetypes.location(entity.id())?;
+        let archetype = inner.archetypes.get(location.archetype);
+        if !self.signature.matches(archetype.signature()) {
+            return None;
+        }
+
+        if !F::filter(&F::borrow_column(archetype), location.row, inner.last_run())
+            || !inner.is_matched_by(&self.signature, entity)
+        {
+            return None;
+        }
+
+        let mut columns = T::borrow_column(archetype, inner.change_tick());
+        Some(f(T::fetch(&mut columns, location.row)))
+    }
+
+    /// The entities matching the query.
+    pub fn entities(&self) -> Vec<Entity> {
+        let inner = self.em.inner.borrow();
+        let last_run = inner.last_run();
+        let mut matched = Vec::new();
+        for archetype in inner.archetypes.iter() {
+            if !self.signature.matches(archetype.signature()) {
+                continue;
+            }
+
+            let entities = archetype.entities().borrow();
+            if entities.is_empty() {
+                continue;
+            }
+
+            let filter = F::borrow_column(archetype);
+            matched.extend(
+                entities
+                    .iter()
+                    .enumerate()
+                    .filter(|(row, entity)| {
+                        F::filter(&filter, *row, last_run)
+                            && inner.is_matched_by(&self.signature, **entity)
+                    })
+                    .map(|(_, entity)| *entity),
+            );
+        }
+        matched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity_manager::impl_component;
+
+    struct Position(u32);
+    struct Velocity(u32);
+    impl_component!(Position, Velocity);
+
+    #[test]
+    fn disjoint_and_shared_accesses_are_allowed() {
+        let em = EntityManager::new();
+        let entity = em.create_entity();
+        em.add_bundle(entity, (Position(1), Velocity(2)));
+        em.update();
+
+        let query = Query::<(&mut Position, &Velocity)>::new(&em);
+        query.for_each(|_, (mut position, velocity)| position.0 += velocity.0);
+        Query::<(&Position, Option<&Position>)>::new(&em);
+
+        assert_eq!(Query::<&Position>::new(&em).get(entity, |p| p.0), Some(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "borrows a component mutably along with another borrow")]
+    fn mutable_and_shared_access_to_the_same_component_panics() {
+        Query::<(&mut Position, &Position)>::new(&EntityManager::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "borrows a component mutably along with another borrow")]
+    fn nested_mutable_accesses_to_the_same_component_panic() {
+        Query::<(&Velocity, (Option<&mut Position>, &mut Position))>::new(&EntityManager::new());
+    }
+
+    #[test]
+    fn entities_waiting_to_be_added_are_skipped_like_signature_lookups() {
+        let em = EntityManager::new();
+        let entity = em.create_entity();
+        em.add_component(entity, Position(1));
+        let query = Query::<&Position>::new(&em);
+        let mut signature = ComponentSignature::default();
+        signature.require_component::<Position>();
+
+        let mut visited = Vec::new();
+        query.for_each(|entity, _| visited.push(entity));
+        assert!(visited.is_empty());
+        assert!(query.entities().is_empty());
+        assert_eq!(query.get(entity, |position| position.0), None);
+        assert!(em.get_entities_with_signature(&signature).is_empty());
+
+        em.update();
+
+        query.for_each(|entity, _| visited.push(entity));
+        assert_eq!(visited, vec![entity]);
+        assert_eq!(query.entities(), vec![entity]);
+        assert_eq!(query.get(entity, |position| position.0), Some(1));
+        assert_eq!(em.get_entities_with_signature(&signature), vec![entity]);
+    }
 }
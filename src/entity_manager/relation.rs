@@ -0,0 +1,178 @@
+use std::{any::TypeId, collections::HashMap};
+
+use super::{em::EntityManagerInner, Entity};
+
+/// What happens to the sources of a relation when its target is despawned.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RelationCleanup {
+    /// Removes the relation from the sources, which stay alive.
+    RemoveRelation,
+    /// Despawns the sources along with the target.
+    DespawnSources,
+}
+
+/// A kind of relation between two entities, such as `OwnedBy` or `Targets`. Relations are pairs
+/// of a relation type and a target entity, added to a source entity with
+/// `EntityManager::add_relation`. An entity can have the same relation with several targets.
+///
+/// ```ignore
+/// struct OwnedBy;
+///
+/// impl Relation for OwnedBy {
+///     const ON_TARGET_DESPAWN: RelationCleanup = RelationCleanup::DespawnSources;
+/// }
+///
+/// em.add_relation::<OwnedBy>(projectile, player);
+/// let projectiles = em.sources::<OwnedBy>(player);
+/// ```
+///
+/// Despawning the source of a relation always removes it. Despawning the target applies the
+/// cleanup policy of the relation type, which removes the relation by default.
+pub trait Relation: 'static {
+    const ON_TARGET_DESPAWN: RelationCleanup = RelationCleanup::RemoveRelation;
+}
+
+/// The pairs of a single relation type, indexed in both directions.
+struct RelationTable {
+    cleanup: RelationCleanup,
+    // The targets of each source, in the order they were added.
+    targets: HashMap<Entity, Vec<Entity>>,
+    // The sources of each target, in the order they were added.
+    sources: HashMap<Entity, Vec<Entity>>,
+}
+
+impl RelationTable {
+    fn remove(&mut self, source: Entity, target: Entity) -> bool {
+        let Some(targets) = self.targets.get_mut(&source) else {
+            return false;
+        };
+        let Some(index) = targets.iter().position(|t| *t == target) else {
+            return false;
+        };
+        targets.remove(index);
+        if targets.is_empty() {
+            self.targets.remove(&source);
+        }
+
+        if let Some(sources) = self.sources.get_mut(&target) {
+            sources.retain(|s| *s != source);
+            if sources.is_empty() {
+                self.sources.remove(&target);
+            }
+        }
+        true
+    }
+}
+
+/// The relations between entities, by relation type.
+#[derive(Default)]
+pub(crate) struct RelationStorage {
+    tables: HashMap<TypeId, RelationTable>,
+}
+
+impl RelationStorage {
+    /// Adds the pair to the relations of `source`. Returns `false` if it was already there.
+    pub fn add<R: Relation>(&mut self, source: Entity, target: Entity) -> bool {
+        let table = self
+            .tables
+            .entry(TypeId::of::<R>())
+            .or_insert_with(|| RelationTable {
+                cleanup: R::ON_TARGET_DESPAWN,
+                targets: HashMap::new(),
+                sources: HashMap::new(),
+            });
+
+        let targets = table.targets.entry(source).or_default();
+        if targets.contains(&target) {
+            return false;
+        }
+        targets.push(target);
+        table.sources.entry(target).or_default().push(source);
+        true
+    }
+
+    pub fn remove<R: Relation>(&mut self, source: Entity, target: Entity) -> bool {
+        self.tables
+            .get_mut(&TypeId::of::<R>())
+            .is_some_and(|table| table.remove(source, target))
+    }
+
+    pub fn targets<R: Relation>(&self, source: Entity) -> &[Entity] {
+        self.tables
+            .get(&TypeId::of::<R>())
+            .and_then(|table| table.targets.get(&source))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    pub fn sources<R: Relation>(&self, target: Entity) -> &[Entity] {
+        self.tables
+            .get(&TypeId::of::<R>())
+            .and_then(|table| table.sources.get(&target))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    pub fn pairs<R: Relation>(&self) -> Vec<(Entity, Entity)> {
+        let Some(table) = self.tables.get(&TypeId::of::<R>()) else {
+            return Vec::new();
+        };
+        table
+            .targets
+            .iter()
+            .flat_map(|(source, targets)| targets.iter().map(|target| (*source, *target)))
+            .collect()
+    }
+
+    /// Removes every relation from or to a despawned entity. Returns the sources that must be
+    /// despawned along with it.
+    pub fn remove_entity(&mut self, entity: Entity) -> Vec<Entity> {
+        let mut despawned_sources = Vec::new();
+        for table in self.tables.values_mut() {
+            for target in table.targets.get(&entity).cloned().unwrap_or_default() {
+                table.remove(entity, target);
+            }
+            for source in table.sources.get(&entity).cloned().unwrap_or_default() {
+                table.remove(source, entity);
+                if table.cleanup == RelationCleanup::DespawnSources {
+                    despawned_sources.push(source);
+                }
+            }
+        }
+        despawned_sources
+    }
+}
+
+impl EntityManagerInner {
+    /// Adds the relation `R` from `source` to `target`. Returns `false` if either entity is not
+    /// alive, or if the relation already exists.
+    pub fn add_relation<R: Relation>(&mut self, source: Entity, target: Entity) -> bool {
+        if !self.is_alive(source) || !self.is_alive(target) {
+            return false;
+        }
+        self.relations.add::<R>(source, target)
+    }
+
+    /// Removes the relation `R` from `source` to `target`. Returns `false` if it didn't exist.
+    pub fn remove_relation<R: Relation>(&mut self, source: Entity, target: Entity) -> bool {
+        self.relations.remove::<R>(source, target)
+    }
+
+    pub fn has_relation<R: Relation>(&self, source: Entity, target: Entity) -> bool {
+        self.relations.targets::<R>(source).contains(&target)
+    }
+
+    pub fn targets<R: Relation>(&self, source: Entity) -> Vec<Entity> {
+        self.relations.targets::<R>(source).to_vec()
+    }
+
+    pub fn sources<R: Relation>(&self, target: Entity) -> Vec<Entity> {
+        self.relations.sources::<R>(target).to_vec()
+    }
+
+    /// Removes the relations of a despawning entity, despawning the sources whose relation type
+    /// asks for it.
+    pub(crate) fn cleanup_relations(&mut self, entity: Entity) {
+        for source in self.relations.remove_entity(entity) {
+            self.despawn(source);
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use std::{any::Any, collections::HashMap, marker::PhantomData, rc::Rc};
+
+use super::{archetype::ComponentCell, Component, ComponentTypeId, Entity, EntityManager, Tick};
+
+/// The components of type `C` removed from entities, with the tick they were removed at. The cells
+/// are kept as they were in the column, as `ComponentRef` handles may still share them.
+pub(crate) struct RemovedBuffer<C> {
+    removed: Vec<(Entity, Rc<ComponentCell<C>>, Tick)>,
+}
+
+impl<C> RemovedBuffer<C> {
+    pub fn push(&mut self, entity: Entity, cell: Rc<ComponentCell<C>>, tick: Tick) {
+        self.removed.push((entity, cell, tick));
+    }
+}
+
+/// Type erased operations over a `RemovedBuffer`.
+pub(crate) trait AnyRemovedBuffer {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    /// Drops the components removed before `tick`.
+    fn clear_before(&mut self, tick: Tick);
+}
+
+impl<C: 'static> AnyRemovedBuffer for RemovedBuffer<C> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn clear_before(&mut self, tick: Tick) {
+        self.removed
+            .retain(|(_, _, removed_at)| *removed_at >= tick);
+    }
+}
+
+/// The components removed from entities, by component type. Removed components are kept for two
+/// frames, so every system runs at least once after a removal while it is still stored.
+#[derive(Default)]
+pub(crate) struct RemovedComponentsStorage {
+    buffers: HashMap<ComponentTypeId, Box<dyn AnyRemovedBuffer>>,
+    // The tick at the start of the previous frame.
+    previous_frame: Tick,
+}
+
+impl RemovedComponentsStorage {
+    pub fn get<C: 'static>(&self, type_id: ComponentTypeId) -> Option<&RemovedBuffer<C>> {
+        self.buffers
+            .get(&type_id)
+            .map(|b| b.as_any().downcast_ref::<RemovedBuffer<C>>().unwrap())
+    }
+
+    pub fn get_or_insert<C: 'static>(&mut self, type_id: ComponentTypeId) -> &mut RemovedBuffer<C> {
+        self.buffers
+            .entry(type_id)
+            .or_insert_with(|| Box::new(RemovedBuffer::<C> { removed: Vec::new() }))
+            .as_any_mut()
+            .downcast_mut::<RemovedBuffer<C>>()
+            .unwrap()
+    }
+
+    /// Drops the components removed before the previous frame. `tick` is the tick at the start of
+    /// the new frame.
+    pub fn begin_frame(&mut self, tick: Tick) {
+        for buffer in self.buffers.values_mut() {
+            buffer.clear_before(self.previous_frame);
+        }
+        self.previous_frame = tick;
+    }
+}
+
+/// The stream of components of type `C` removed from entities, either by removing the component or
+/// by despawning the entity. Each removal is yielded along with the entity and the final value of
+/// the component.
+///
+/// Inside a system, the stream only yields the removals made since the previous run of the
+/// system, so each removal is read once per system. Outside of systems, it yields the removals of
+/// the current and the previous frame.
+///
+/// The `EntityManager` is borrowed while the stream is read, so structural changes from inside
+/// `for_each` panic.
+pub struct RemovedComponents<C> {
+    em: EntityManager,
+    phantom: PhantomData<C>,
+}
+
+impl<C: Component + 'static> RemovedComponents<C> {
+    pub fn new(em: &EntityManager) -> Self {
+        Self { em: em.clone(), phantom: PhantomData }
+    }
+
+    /// Calls `f` with each entity the component was removed from and the removed component.
+    pub fn for_each(&self, mut f: impl FnMut(Entity, &C)) {
+        let inner = self.em.inner.borrow();
+        let last_run = inner.last_run();
+        let Some(buffer) = inner.removed.get::<C>(C::get_type_id()) else {
+            return;
+        };
+
+        for (entity, cell, removed_at) in &buffer.removed {
+            if *removed_at > last_run {
+                f(*entity, &cell.value.borrow());
+            }
+        }
+    }
+
+    /// The entities the component was removed from.
+    pub fn entities(&self) -> Vec<Entity> {
+        let mut entities = Vec::new();
+        self.for_each(|entity, _| entities.push(entity));
+        entities
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{
+        entity_manager::impl_component, events::EventListener, systems::System, AssetManager,
+        ComponentSignature, EntityComponentSystem, EventBus, Resources,
+    };
+
+    struct Value(u32);
+    impl_component!(Value);
+
+    // Runs `run` with the entity manager on each update.
+    struct FnSystem {
+        signature: ComponentSignature,
+        run: Box<dyn Fn(&EntityManager)>,
+    }
+
+    impl EventListener for FnSystem {}
+
+    impl System for FnSystem {
+        fn signature(&self) -> &ComponentSignature {
+            &self.signature
+        }
+
+        fn add_entity(&mut self, _entity: Entity) {}
+
+        fn remove_entity(&mut self, _entity: Entity) {}
+
+        fn update(
+            &self,
+            _delta_time: Duration,
+            _asset_manager: &AssetManager,
+            em: EntityManager,
+            _event_bus: Rc<RefCell<EventBus>>,
+            _resources: Rc<RefCell<Resources>>,
+        ) {
+            (self.run)(&em);
+        }
+    }
+
+    #[test]
+    fn removals_are_read_once_by_systems_before_and_after_the_removal() {
+        let mut ecs = EntityComponentSystem::new();
+        let frame = Rc::new(Cell::new(0));
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let em = ecs.entity_manager();
+        let entity = em.create_entity();
+        em.add_component(entity, Value(7));
+
+        let reader = |name: &'static str| {
+            let (frame, log) = (frame.clone(), log.clone());
+            FnSystem {
+                signature: ComponentSignature::default(),
+                run: Box::new(move |em| {
+                    RemovedComponents::<Value>::new(em).for_each(|entity, value| {
+                        log.borrow_mut().push((frame.get(), name, entity, value.0));
+                    });
+                }),
+            }
+        };
+        ecs.add_system(reader("before"));
+        let remover_frame = frame.clone();
+        ecs.add_system(FnSystem {
+            signature: ComponentSignature::default(),
+            run: Box::new(move |em| {
+                if remover_frame.get() == 1 {
+                    em.remove_component::<Value>(entity);
+                }
+            }),
+        });
+        ecs.add_system(reader("after"));
+
+        for current in 0..4 {
+            frame.set(current);
+            ecs.update(Duration::ZERO);
+            let outside = RemovedComponents::<Value>::new(&em).entities();
+            match current {
+                1 | 2 => assert_eq!(outside, vec![entity]),
+                _ => assert!(outside.is_empty()),
+            }
+        }
+
+        assert_eq!(
+            *log.borrow(),
+            vec![(1, "after", entity, 7), (2, "before", entity, 7)]
+        );
+    }
+}
@@ -1,56 +1,196 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
-use super::{Entity, EntityId};
+use super::Entity;
 
+/// The tags of the entities. An entity can have one unique tag, set with `set_tag`, that no other
+/// entity has at the same time, such as "player". It can also have any number of labels, added
+/// with `add_label`, that any number of entities can share, such as "flammable".
+///
+/// Lookups by tag, such as `has_tag`, `entities_with_tag`, and the tags required by queries and
+/// system signatures, match both unique tags and labels.
+///
+/// Tags are changed through the methods of the `EntityManager` and `Commands`, such as
+/// `EntityManager::set_tag`, which reject entities that are not alive.
 #[derive(Clone, Default)]
 pub struct TagManager {
     inner: Rc<RefCell<TagManagerInner>>,
 }
 
 impl TagManager {
-    pub fn set_tag(&self, entity: Entity, tag: &str) {
+    /// Sets the unique tag of the entity, replacing its previous one. The entity that had `tag`
+    /// before loses it.
+    pub(crate) fn set_tag(&self, entity: Entity, tag: &str) {
         self.inner.borrow_mut().set_tag(entity, tag);
     }
 
-    pub fn remove_tag(&self, entity: Entity) {
+    /// Removes the unique tag of the entity. Its labels are kept.
+    pub(crate) fn remove_tag(&self, entity: Entity) {
         self.inner.borrow_mut().remove_tag(entity);
     }
 
+    /// Adds `label` to the labels of the entity.
+    pub(crate) fn add_label(&self, entity: Entity, label: &str) {
+        self.inner.borrow_mut().add_label(entity, label);
+    }
+
+    pub(crate) fn remove_label(&self, entity: Entity, label: &str) {
+        self.inner.borrow_mut().remove_label(entity, label);
+    }
+
+    /// Removes the unique tag and the labels of the entity.
+    pub(crate) fn remove_entity(&self, entity: Entity) {
+        self.inner.borrow_mut().remove_entity(entity);
+    }
+
+    /// Marks `tag` as set by an `EntityBuilder` that hasn't created its entity yet. Returns `false`
+    /// if another builder already set it.
+    pub(crate) fn reserve_pending(&self, tag: &str) -> bool {
+        self.inner.borrow_mut().pending.insert(tag.to_string())
+    }
+
+    pub(crate) fn release_pending(&self, tag: &str) {
+        self.inner.borrow_mut().pending.remove(tag);
+    }
+
+    /// Returns `true` if `tag` is the unique tag or one of the labels of the entity.
     pub fn has_tag(&self, entity: Entity, tag: &str) -> bool {
         self.inner.borrow().has_tag(entity, tag)
     }
 
+    /// The entity whose unique tag is `tag`.
     pub fn get_entity(&self, tag: &str) -> Option<Entity> {
         self.inner.borrow().get_entity(tag)
     }
+
+    /// The unique tag of the entity.
+    pub fn get_tag(&self, entity: Entity) -> Option<String> {
+        self.inner.borrow().get_tag(entity)
+    }
+
+    /// The labels of the entity, in the order they were added.
+    pub fn labels_of(&self, entity: Entity) -> Vec<String> {
+        self.inner.borrow().labels_of(entity)
+    }
+
+    /// The entities with `tag`, either as their unique tag or as a label. The entity whose unique
+    /// tag it is comes first, followed by the labeled ones in the order they were labeled.
+    pub fn entities_with_tag(&self, tag: &str) -> Vec<Entity> {
+        self.inner.borrow().entities_with_tag(tag)
+    }
+
+    /// Takes the entities whose tags changed since the last call.
+    pub(crate) fn take_changed(&self) -> HashSet<Entity> {
+        std::mem::take(&mut self.inner.borrow_mut().changed)
+    }
 }
 
 #[derive(Default)]
 pub struct TagManagerInner {
-    entity_tag: HashMap<EntityId, String>,
-    tag_entity: HashMap<String, EntityId>,
+    entity_tag: HashMap<Entity, String>,
+    tag_entity: HashMap<String, Entity>,
+    // The labels of each entity and the entities with each label, in the order they were added.
+    entity_labels: HashMap<Entity, Vec<String>>,
+    label_entities: HashMap<String, Vec<Entity>>,
+    // The entities whose tags changed, whose system membership must be updated.
+    changed: HashSet<Entity>,
+    // The tags set by the `EntityBuilder`s that haven't created their entity yet.
+    pending: HashSet<String>,
 }
 
 impl TagManagerInner {
     pub fn set_tag(&mut self, entity: Entity, tag: &str) {
-        self.entity_tag.insert(entity.id(), tag.to_string());
-        self.tag_entity.insert(tag.to_string(), entity.id());
+        if self.entity_tag.get(&entity).is_some_and(|t| t == tag) {
+            return;
+        }
+
+        self.remove_tag(entity);
+        if let Some(previous) = self.tag_entity.get(tag).copied() {
+            self.remove_tag(previous);
+        }
+        self.entity_tag.insert(entity, tag.to_string());
+        self.tag_entity.insert(tag.to_string(), entity);
+        self.changed.insert(entity);
     }
 
     pub fn remove_tag(&mut self, entity: Entity) {
-        if let Some(tag) = self.entity_tag.remove(&entity.id()) {
+        if let Some(tag) = self.entity_tag.remove(&entity) {
             self.tag_entity.remove(&tag);
+            self.changed.insert(entity);
         }
     }
 
-    pub fn has_tag(&self, entity: Entity, tag: &str) -> bool {
-        match self.entity_tag.get(&entity.id()) {
-            Some(t) => t == tag,
-            None => false,
+    pub fn add_label(&mut self, entity: Entity, label: &str) {
+        let labels = self.entity_labels.entry(entity).or_default();
+        if labels.iter().any(|l| l == label) {
+            return;
+        }
+        labels.push(label.to_string());
+        self.label_entities
+            .entry(label.to_string())
+            .or_default()
+            .push(entity);
+        self.changed.insert(entity);
+    }
+
+    pub fn remove_label(&mut self, entity: Entity, label: &str) {
+        let Some(labels) = self.entity_labels.get_mut(&entity) else {
+            return;
+        };
+        let Some(index) = labels.iter().position(|l| l == label) else {
+            return;
+        };
+        labels.remove(index);
+        if labels.is_empty() {
+            self.entity_labels.remove(&entity);
+        }
+
+        if let Some(entities) = self.label_entities.get_mut(label) {
+            entities.retain(|e| *e != entity);
+            if entities.is_empty() {
+                self.label_entities.remove(label);
+            }
+        }
+        self.changed.insert(entity);
+    }
+
+    pub fn remove_entity(&mut self, entity: Entity) {
+        self.remove_tag(entity);
+        for label in self.labels_of(entity) {
+            self.remove_label(entity, &label);
         }
     }
 
+    pub fn has_tag(&self, entity: Entity, tag: &str) -> bool {
+        self.entity_tag.get(&entity).is_some_and(|t| t == tag)
+            || self
+                .entity_labels
+                .get(&entity)
+                .is_some_and(|labels| labels.iter().any(|l| l == tag))
+    }
+
     pub fn get_entity(&self, tag: &str) -> Option<Entity> {
-        self.tag_entity.get(tag).map(|id| Entity::new(*id))
+        self.tag_entity.get(tag).copied()
+    }
+
+    pub fn get_tag(&self, entity: Entity) -> Option<String> {
+        self.entity_tag.get(&entity).cloned()
+    }
+
+    pub fn labels_of(&self, entity: Entity) -> Vec<String> {
+        self.entity_labels.get(&entity).cloned().unwrap_or_default()
+    }
+
+    pub fn entities_with_tag(&self, tag: &str) -> Vec<Entity> {
+        let mut entities: Vec<Entity> = self.get_entity(tag).into_iter().collect();
+        for entity in self.label_entities.get(tag).into_iter().flatten() {
+            if !entities.contains(entity) {
+                entities.push(*entity);
+            }
+        }
+        entities
     }
 }
@@ -1,53 +1,738 @@
 use std::{
     any::{Any, TypeId},
-    cell::RefCell,
-    collections::HashMap,
-    rc::Rc,
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
+    rc::{Rc, Weak},
 };
 
-use crate::{systems::System, EntityManager};
+use crate::{systems::System, Entity, EntityManager};
 
-type SystemRef = Rc<RefCell<Box<(dyn System + 'static)>>>;
+mod channel;
+
+pub use channel::{EventReader, EventWriter, Events};
+
+type SystemRef = Rc<RefCell<Box<dyn System + 'static>>>;
 
 pub struct Event {
     data: Box<dyn Any + 'static>,
+    stopped: Cell<bool>,
+    // The entity the event is aimed at, and the one whose observers are receiving it.
+    target: Option<Entity>,
+    current: Cell<Option<Entity>>,
 }
 
 impl Event {
     pub fn new<T: Clone + 'static>(data: T) -> Self {
-        Self { data: Box::new(data) }
+        Self {
+            data: Box::new(data),
+            stopped: Cell::new(false),
+            target: None,
+            current: Cell::new(None),
+        }
+    }
+
+    fn with_target<T: Clone + 'static>(data: T, target: Entity) -> Self {
+        Self { target: Some(target), ..Self::new(data) }
     }
+
     pub fn get_data<T: Clone + 'static>(&self) -> Option<&T> {
         self.data.downcast_ref::<T>()
     }
+
+    /// The entity the event is aimed at, for events emitted with `EventBus::emit_to`.
+    pub fn target(&self) -> Option<Entity> {
+        self.target
+    }
+
+    /// Stops the dispatch of the event: the listeners with a lower priority don't receive it, and
+    /// it doesn't bubble further up the hierarchy.
+    pub fn stop_propagation(&self) {
+        self.stopped.set(true);
+    }
+
+    pub fn is_propagation_stopped(&self) -> bool {
+        self.stopped.get()
+    }
 }
 
 pub trait EventListener {
-    fn on_event(&self, _em: EntityManager, _event: &Event) {}
+    /// Called with each event of the types the listener subscribed to. `event_bus` is the bus
+    /// dispatching the event, on which the listener can emit further events.
+    fn on_event(&self, _em: EntityManager, _event_bus: &EventBus, _event: &Event) {}
+}
+
+/// When the listeners receive the emitted events.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DispatchMode {
+    /// Listeners receive the events during `emit`.
+    #[default]
+    Immediate,
+    /// `emit` adds the events to a queue, dispatched by `EntityComponentSystem::update` at the
+    /// start of the update and after each system runs. The events emitted by listeners are
+    /// dispatched in the same pass.
+    Queued,
+}
+
+/// An event aimed at an entity, received by the observers of that entity, and by the observers of
+/// its ancestors when it bubbles.
+pub struct EntityEvent<'a, T> {
+    pub data: &'a T,
+    /// The entity the event is aimed at.
+    pub target: Entity,
+    /// The entity whose observer receives the event: the target, or one of its ancestors.
+    pub current: Entity,
+    event: &'a Event,
+}
+
+impl<T> EntityEvent<'_, T> {
+    /// Stops the dispatch of the event: the observers with a lower priority don't receive it, and
+    /// it doesn't bubble further up the hierarchy.
+    pub fn stop_propagation(&self) {
+        self.event.stop_propagation();
+    }
 }
 
-#[derive(Default)]
+type Callback = Rc<dyn Fn(EntityManager, &EventBus, &Event)>;
+
+struct Listener {
+    id: u64,
+    priority: i32,
+    callback: Callback,
+    // Cleared when the listener unsubscribes, so it stops receiving the event being dispatched.
+    active: Cell<bool>,
+}
+
+/// The events a listener receives: all the events of a type, or the ones aimed at an entity.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+struct ListenerKey {
+    type_id: TypeId,
+    target: Option<Entity>,
+}
+
+/// The listeners of each key, sorted by decreasing priority, and in subscription order for equal
+/// priorities.
+type ListenerMap = HashMap<ListenerKey, Vec<Rc<Listener>>>;
+
+/// Keeps a subscription made with `EventBus::subscribe` or `EventBus::observe` alive. Dropping the handle unsubscribes
+/// the listener.
+#[must_use = "dropping the handle unsubscribes the listener right away"]
+pub struct SubscriptionHandle {
+    listeners: Weak<RefCell<ListenerMap>>,
+    key: ListenerKey,
+    id: u64,
+}
+
+impl SubscriptionHandle {
+    /// Keeps the listener subscribed for as long as the event bus lives.
+    pub fn forget(self) {
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for SubscriptionHandle {
+    fn drop(&mut self) {
+        let Some(listeners) = self.listeners.upgrade() else {
+            return;
+        };
+        let mut listeners = listeners.borrow_mut();
+        let Some(key_listeners) = listeners.get_mut(&self.key) else {
+            return;
+        };
+        key_listeners.retain(|listener| {
+            if listener.id == self.id {
+                listener.active.set(false);
+            }
+            listener.id != self.id
+        });
+        if key_listeners.is_empty() {
+            listeners.remove(&self.key);
+        }
+    }
+}
+
+struct QueuedEvent {
+    type_id: TypeId,
+    event: Event,
+    // The number of events that led to this one, 0 for events not emitted by a listener.
+    depth: usize,
+    bubble: bool,
+}
+
+/// Dispatches events to the listeners subscribed to their type. Listeners with a higher priority
+/// receive the events first, and can stop their propagation to the others with
+/// `Event::stop_propagation`. Listeners with the same priority receive them in the order they
+/// subscribed.
+///
+/// Listeners are either systems, subscribed by `EntityComponentSystem::add_system` to the types
+/// of their `get_event_type`, or closures subscribed with `subscribe`. Subscriptions last until
+/// they are dropped, across frames. Listeners can subscribe and unsubscribe while receiving an
+/// event, which only affects the events dispatched afterwards, except that unsubscribed listeners
+/// stop receiving the current event.
+///
+/// Events can also be aimed at an entity with `emit_to`, and are then only received by the
+/// observers of that entity, subscribed with `observe`. With `emit_bubbling`, the event then
+/// bubbles up to the observers of the parent of the entity, and so on up to the root, unless an
+/// observer stops its propagation.
+///
+/// Listeners can emit events while receiving one. The chains of events emitted this way are cut
+/// once they are longer than the maximum cascade depth, to break infinite loops.
 pub struct EventBus {
-    listeners: HashMap<TypeId, Vec<SystemRef>>,
+    listeners: Rc<RefCell<ListenerMap>>,
+    next_listener_id: Cell<u64>,
+    mode: DispatchMode,
+    queue: RefCell<VecDeque<QueuedEvent>>,
+    // The depth of the event being dispatched, if any.
+    dispatch_depth: Cell<Option<usize>>,
+    max_cascade_depth: usize,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self {
+            listeners: Default::default(),
+            next_listener_id: Cell::new(0),
+            mode: DispatchMode::default(),
+            queue: RefCell::new(VecDeque::new()),
+            dispatch_depth: Cell::new(None),
+            max_cascade_depth: Self::DEFAULT_MAX_CASCADE_DEPTH,
+        }
+    }
 }
 
 impl EventBus {
-    pub fn subscribe_type(&mut self, type_id: TypeId, listener: SystemRef) {
-        let listeners = self.listeners.entry(type_id).or_default();
-        listeners.push(listener);
+    pub const DEFAULT_MAX_CASCADE_DEPTH: usize = 8;
+
+    /// Subscribes the system to the events of type `type_id`, for as long as the event bus lives.
+    pub fn subscribe_type(&self, type_id: TypeId, listener: SystemRef) {
+        self.subscribe_type_with_priority(type_id, listener, 0);
+    }
+
+    /// Subscribes the system to the events of type `type_id`, received before the listeners with
+    /// a lower priority, for as long as the event bus lives.
+    pub fn subscribe_type_with_priority(
+        &self,
+        type_id: TypeId,
+        listener: SystemRef,
+        priority: i32,
+    ) {
+        self.add_listener(
+            ListenerKey { type_id, target: None },
+            priority,
+            Rc::new(move |em, event_bus, event| {
+                listener.borrow().on_event(em, event_bus, event);
+            }),
+        );
+    }
+
+    /// Calls `f` with each event of type `T` until the returned handle is dropped.
+    ///
+    /// ```ignore
+    /// let _subscription = event_bus.borrow().subscribe::<CollisionEvent>(|em, collision| {
+    ///     play_sound("hit");
+    /// });
+    /// ```
+    pub fn subscribe<T: Clone + 'static>(
+        &self,
+        f: impl Fn(EntityManager, &T) + 'static,
+    ) -> SubscriptionHandle {
+        self.subscribe_with_priority(0, f)
+    }
+
+    /// Calls `f` with each event of type `T` until the returned handle is dropped, before the
+    /// listeners with a lower priority.
+    pub fn subscribe_with_priority<T: Clone + 'static>(
+        &self,
+        priority: i32,
+        f: impl Fn(EntityManager, &T) + 'static,
+    ) -> SubscriptionHandle {
+        let key = ListenerKey { type_id: TypeId::of::<T>(), target: None };
+        let id = self.add_listener(
+            key,
+            priority,
+            Rc::new(move |em, _event_bus, event| {
+                if let Some(data) = event.get_data::<T>() {
+                    f(em, data);
+                }
+            }),
+        );
+        SubscriptionHandle { listeners: Rc::downgrade(&self.listeners), key, id }
+    }
+
+    /// Calls `f` with each event of type `T` aimed at `entity`, or bubbling up from one of its
+    /// descendants, until the returned handle is dropped. Observers of despawned entities are
+    /// removed at the next update.
+    ///
+    /// ```ignore
+    /// let _observer = event_bus.borrow().observe::<Hit>(tank, |em, hit| {
+    ///     tracing::info!("{:?} hit {:?} for {}", hit.target, hit.current, hit.data.damage);
+    /// });
+    /// event_bus.borrow().emit_bubbling(em, cannon, Hit { damage: 10 });
+    /// ```
+    pub fn observe<T: Clone + 'static>(
+        &self,
+        entity: Entity,
+        f: impl Fn(EntityManager, &EntityEvent<T>) + 'static,
+    ) -> SubscriptionHandle {
+        self.observe_with_priority(entity, 0, f)
+    }
+
+    /// Like `observe`, but `f` receives the events before the observers of `entity` with a lower
+    /// priority.
+    pub fn observe_with_priority<T: Clone + 'static>(
+        &self,
+        entity: Entity,
+        priority: i32,
+        f: impl Fn(EntityManager, &EntityEvent<T>) + 'static,
+    ) -> SubscriptionHandle {
+        let key = ListenerKey { type_id: TypeId::of::<T>(), target: Some(entity) };
+        let id = self.add_listener(
+            key,
+            priority,
+            Rc::new(move |em, _event_bus, event| {
+                let (Some(data), Some(target), Some(current)) =
+                    (event.get_data::<T>(), event.target, event.current.get())
+                else {
+                    return;
+                };
+                f(em, &EntityEvent { data, target, current, event });
+            }),
+        );
+        SubscriptionHandle { listeners: Rc::downgrade(&self.listeners), key, id }
+    }
+
+    /// Removes the observers of the entities that are no longer alive.
+    pub(crate) fn remove_dead_observers(&self, em: &EntityManager) {
+        self.listeners.borrow_mut().retain(|key, listeners| {
+            let alive = key.target.is_none_or(|entity| em.is_alive(entity));
+            if !alive {
+                for listener in listeners {
+                    listener.active.set(false);
+                }
+            }
+            alive
+        });
+    }
+
+    fn add_listener(&self, key: ListenerKey, priority: i32, callback: Callback) -> u64 {
+        let id = self.next_listener_id.get();
+        self.next_listener_id.set(id + 1);
+
+        let mut listeners = self.listeners.borrow_mut();
+        let listeners = listeners.entry(key).or_default();
+        let index = listeners.partition_point(|l| l.priority >= priority);
+        let listener = Listener { id, priority, callback, active: Cell::new(true) };
+        listeners.insert(index, Rc::new(listener));
+        id
+    }
+
+    pub fn dispatch_mode(&self) -> DispatchMode {
+        self.mode
+    }
+
+    pub fn set_dispatch_mode(&mut self, mode: DispatchMode) {
+        self.mode = mode;
+    }
+
+    /// Sets how many events a chain of events can hold, counting the event emitted outside of
+    /// listeners and the ones emitted by listeners while receiving the previous event of the
+    /// chain. Events past this depth are dropped.
+    pub fn set_max_cascade_depth(&mut self, depth: usize) {
+        self.max_cascade_depth = depth;
     }
 
     pub fn emit<T: Clone + 'static>(&self, em: EntityManager, data: T) {
+        self.send::<T>(em, Event::new(data), false);
+    }
+
+    /// Emits an event received only by the observers of `target`.
+    pub fn emit_to<T: Clone + 'static>(&self, em: EntityManager, target: Entity, data: T) {
+        self.send::<T>(em, Event::with_target(data, target), false);
+    }
+
+    /// Emits an event received by the observers of `target`, then by the observers of each of its
+    /// ancestors, from the parent up to the root, until an observer stops its propagation.
+    pub fn emit_bubbling<T: Clone + 'static>(&self, em: EntityManager, target: Entity, data: T) {
+        self.send::<T>(em, Event::with_target(data, target), true);
+    }
+
+    fn send<T: 'static>(&self, em: EntityManager, event: Event, bubble: bool) {
+        let depth = self.dispatch_depth.get().map_or(0, |depth| depth + 1);
+        if depth >= self.max_cascade_depth {
+            tracing::warn!(
+                "dropped event {} past the maximum cascade depth of {}",
+                std::any::type_name::<T>(),
+                self.max_cascade_depth
+            );
+            return;
+        }
+
         let type_id = TypeId::of::<T>();
-        if let Some(listeners) = self.listeners.get(&type_id) {
-            let event = Event::new(data);
-            for listener in listeners {
-                listener.borrow().on_event(em.clone(), &event);
+        match self.mode {
+            DispatchMode::Immediate => self.dispatch(em, type_id, &event, depth, bubble),
+            DispatchMode::Queued => {
+                self.queue
+                    .borrow_mut()
+                    .push_back(QueuedEvent { type_id, event, depth, bubble });
             }
         }
     }
 
+    /// Dispatches the queued events, including the ones emitted by listeners while dispatching
+    /// them, in the order they were emitted.
+    pub fn dispatch_queued(&self, em: EntityManager) {
+        loop {
+            let Some(queued) = self.queue.borrow_mut().pop_front() else {
+                break;
+            };
+            self.dispatch(
+                em.clone(),
+                queued.type_id,
+                &queued.event,
+                queued.depth,
+                queued.bubble,
+            );
+        }
+    }
+
+    /// Returns `true` if no events are waiting to be dispatched.
+    pub fn is_queue_empty(&self) -> bool {
+        self.queue.borrow().is_empty()
+    }
+
+    /// Unsubscribes every listener, including the systems.
     pub fn clear(&mut self) {
-        self.listeners.clear();
+        self.listeners.borrow_mut().clear();
+    }
+
+    fn dispatch(
+        &self,
+        em: EntityManager,
+        type_id: TypeId,
+        event: &Event,
+        depth: usize,
+        bubble: bool,
+    ) {
+        let outer_depth = self.dispatch_depth.replace(Some(depth));
+        match event.target {
+            None => self.notify(&em, ListenerKey { type_id, target: None }, event),
+            Some(target) => {
+                let mut current = Some(target);
+                while let Some(entity) = current {
+                    if !em.is_alive(entity) {
+                        break;
+                    }
+                    event.current.set(Some(entity));
+                    self.notify(&em, ListenerKey { type_id, target: Some(entity) }, event);
+                    if !bubble || event.is_propagation_stopped() {
+                        break;
+                    }
+                    current = em.parent(entity);
+                }
+            }
+        }
+        self.dispatch_depth.set(outer_depth);
+    }
+
+    // Calls the listeners of `key` with the event, until one stops its propagation.
+    fn notify(&self, em: &EntityManager, key: ListenerKey, event: &Event) {
+        // Listeners are taken out of the map, so they can subscribe and unsubscribe while
+        // receiving the event.
+        let Some(listeners) = self.listeners.borrow().get(&key).cloned() else {
+            return;
+        };
+        for listener in listeners {
+            if !listener.active.get() {
+                continue;
+            }
+            (listener.callback)(em.clone(), self, event);
+            if event.is_propagation_stopped() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ComponentSignature;
+
+    // A system stopping the propagation of every event it receives.
+    struct Stopper(ComponentSignature);
+
+    impl System for Stopper {
+        fn signature(&self) -> &ComponentSignature {
+            &self.0
+        }
+
+        fn add_entity(&mut self, _entity: Entity) {}
+
+        fn remove_entity(&mut self, _entity: Entity) {}
+    }
+
+    impl EventListener for Stopper {
+        fn on_event(&self, _em: EntityManager, _event_bus: &EventBus, event: &Event) {
+            event.stop_propagation();
+        }
+    }
+
+    // Subscribes a listener recording the events of type `u32` it receives, tagged with `name`.
+    fn record(
+        event_bus: &EventBus,
+        log: &Rc<RefCell<Vec<(&'static str, u32)>>>,
+        name: &'static str,
+        priority: i32,
+    ) -> SubscriptionHandle {
+        let log = log.clone();
+        event_bus.subscribe_with_priority::<u32>(priority, move |_, event| {
+            log.borrow_mut().push((name, *event));
+        })
+    }
+
+    #[test]
+    fn queued_events_are_dispatched_in_emission_order() {
+        let em = EntityManager::new();
+        let event_bus = Rc::new(RefCell::new(EventBus::default()));
+        event_bus
+            .borrow_mut()
+            .set_dispatch_mode(DispatchMode::Queued);
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let _recorder = record(&event_bus.borrow(), &log, "recorder", 0);
+        let emitter_bus = event_bus.clone();
+        let _emitter = event_bus.borrow().subscribe::<u32>(move |em, event| {
+            if *event == 1 {
+                emitter_bus.borrow().emit(em, 10u32);
+            }
+        });
+
+        event_bus.borrow().emit(em.clone(), 1u32);
+        event_bus.borrow().emit(em.clone(), 2u32);
+        assert!(log.borrow().is_empty());
+        event_bus.borrow().dispatch_queued(em);
+
+        let received: Vec<u32> = log.borrow().iter().map(|(_, event)| *event).collect();
+        assert_eq!(received, vec![1, 2, 10]);
+        assert!(event_bus.borrow().is_queue_empty());
+    }
+
+    #[test]
+    fn higher_priorities_receive_events_first() {
+        let em = EntityManager::new();
+        let event_bus = EventBus::default();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let _low = record(&event_bus, &log, "low", -1);
+        let _first = record(&event_bus, &log, "first", 0);
+        let _high = record(&event_bus, &log, "high", 5);
+        let _second = record(&event_bus, &log, "second", 0);
+
+        event_bus.emit(em, 1u32);
+
+        assert_eq!(
+            *log.borrow(),
+            vec![("high", 1), ("first", 1), ("second", 1), ("low", 1)]
+        );
+    }
+
+    #[test]
+    fn stopping_propagation_skips_lower_priorities() {
+        let em = EntityManager::new();
+        let event_bus = EventBus::default();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let _high = record(&event_bus, &log, "high", 1);
+        let _low = record(&event_bus, &log, "low", -1);
+        let stopper = Stopper(ComponentSignature::default());
+        event_bus.subscribe_type(
+            TypeId::of::<u32>(),
+            Rc::new(RefCell::new(Box::new(stopper))),
+        );
+
+        event_bus.emit(em, 1u32);
+
+        assert_eq!(*log.borrow(), vec![("high", 1)]);
+    }
+
+    #[test]
+    fn cascades_stop_at_the_maximum_depth() {
+        let em = EntityManager::new();
+        let event_bus = Rc::new(RefCell::new(EventBus::default()));
+        event_bus.borrow_mut().set_max_cascade_depth(3);
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let _recorder = record(&event_bus.borrow(), &log, "recorder", 1);
+        let emitter_bus = event_bus.clone();
+        let _emitter = event_bus.borrow().subscribe::<u32>(move |em, event| {
+            emitter_bus.borrow().emit(em, event + 1);
+        });
+
+        event_bus.borrow().emit(em, 0u32);
+
+        let received: Vec<u32> = log.borrow().iter().map(|(_, event)| *event).collect();
+        assert_eq!(received, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn dropping_the_handle_unsubscribes_and_forgetting_it_keeps_the_listener() {
+        let em = EntityManager::new();
+        let event_bus = EventBus::default();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let dropped = record(&event_bus, &log, "dropped", 0);
+        record(&event_bus, &log, "forgotten", 0).forget();
+
+        event_bus.emit(em.clone(), 1u32);
+        drop(dropped);
+        event_bus.emit(em, 2u32);
+
+        assert_eq!(
+            *log.borrow(),
+            vec![("dropped", 1), ("forgotten", 1), ("forgotten", 2)]
+        );
+    }
+
+    #[test]
+    fn subscriptions_last_across_frames() {
+        let ecs = crate::EntityComponentSystem::new();
+        let em = ecs.entity_manager();
+        let event_bus = ecs.event_bus_cloned();
+        event_bus
+            .borrow_mut()
+            .set_dispatch_mode(DispatchMode::Queued);
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let _subscription = record(&event_bus.borrow(), &log, "listener", 0);
+
+        for frame in 0u32..3 {
+            event_bus.borrow().emit(em.clone(), frame);
+            ecs.update(std::time::Duration::ZERO);
+        }
+
+        assert_eq!(
+            *log.borrow(),
+            vec![("listener", 0), ("listener", 1), ("listener", 2)]
+        );
+    }
+
+    #[test]
+    fn listeners_subscribed_while_dispatching_receive_the_next_events() {
+        let em = EntityManager::new();
+        let event_bus = Rc::new(EventBus::default());
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let late: Rc<RefCell<Option<SubscriptionHandle>>> = Default::default();
+        let (subscriber_bus, subscriber_log, subscriber_late) =
+            (Rc::downgrade(&event_bus), log.clone(), late.clone());
+        let _subscriber = event_bus.subscribe::<u32>(move |_, _| {
+            let mut late = subscriber_late.borrow_mut();
+            if late.is_none() {
+                let event_bus = subscriber_bus.upgrade().unwrap();
+                *late = Some(record(&event_bus, &subscriber_log, "late", -1));
+            }
+        });
+
+        event_bus.emit(em.clone(), 1u32);
+        event_bus.emit(em, 2u32);
+
+        assert_eq!(*log.borrow(), vec![("late", 2)]);
+    }
+
+    #[test]
+    fn listeners_unsubscribed_while_dispatching_miss_the_current_event() {
+        let em = EntityManager::new();
+        let event_bus = EventBus::default();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let victim = Rc::new(RefCell::new(Some(record(&event_bus, &log, "victim", 0))));
+        let unsubscriber_victim = victim.clone();
+        let _unsubscriber = event_bus.subscribe_with_priority::<u32>(1, move |_, _| {
+            unsubscriber_victim.borrow_mut().take();
+        });
+
+        event_bus.emit(em.clone(), 1u32);
+        event_bus.emit(em, 2u32);
+
+        assert!(log.borrow().is_empty());
+        assert!(victim.borrow().is_none());
+    }
+
+    // Spawns a root, its child and its grandchild, returned in that order.
+    fn hierarchy(em: &EntityManager) -> (Entity, Entity, Entity) {
+        let root = em.create_entity();
+        let parent = em.create_entity();
+        let target = em.create_entity();
+        em.set_parent(parent, root);
+        em.set_parent(target, parent);
+        (root, parent, target)
+    }
+
+    // Observes the events of type `u32` of `entity`, recording the entity receiving them and their
+    // target. Stops their propagation if `stop` is `true`.
+    fn record_observed(
+        event_bus: &EventBus,
+        log: &Rc<RefCell<Vec<(Entity, Entity)>>>,
+        entity: Entity,
+        priority: i32,
+        stop: bool,
+    ) -> SubscriptionHandle {
+        let log = log.clone();
+        event_bus.observe_with_priority::<u32>(entity, priority, move |_, event| {
+            log.borrow_mut().push((event.current, event.target));
+            if stop {
+                event.stop_propagation();
+            }
+        })
+    }
+
+    #[test]
+    fn bubbling_events_go_from_the_target_up_to_the_root() {
+        let em = EntityManager::new();
+        let (root, parent, target) = hierarchy(&em);
+        let event_bus = EventBus::default();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let _observers = [root, parent, target]
+            .map(|entity| record_observed(&event_bus, &log, entity, 0, false));
+
+        event_bus.emit_to(em.clone(), target, 1u32);
+        assert_eq!(*log.borrow(), vec![(target, target)]);
+
+        log.borrow_mut().clear();
+        event_bus.emit_bubbling(em, target, 1u32);
+        assert_eq!(
+            *log.borrow(),
+            vec![(target, target), (parent, target), (root, target)]
+        );
+    }
+
+    #[test]
+    fn stopping_propagation_stops_the_bubbling() {
+        let em = EntityManager::new();
+        let (root, parent, target) = hierarchy(&em);
+        let event_bus = EventBus::default();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let _target = record_observed(&event_bus, &log, target, 0, false);
+        let _stopper = record_observed(&event_bus, &log, parent, 1, true);
+        let _skipped = record_observed(&event_bus, &log, parent, 0, false);
+        let _root = record_observed(&event_bus, &log, root, 0, false);
+
+        event_bus.emit_bubbling(em, target, 1u32);
+
+        assert_eq!(*log.borrow(), vec![(target, target), (parent, target)]);
+    }
+
+    #[test]
+    fn observers_of_despawned_entities_are_removed_at_the_next_update() {
+        let ecs = crate::EntityComponentSystem::new();
+        let em = ecs.entity_manager();
+        let entity = em.create_entity();
+        let event_bus = ecs.event_bus_cloned();
+        let observer_state = Rc::new(());
+        let captured = observer_state.clone();
+        event_bus
+            .borrow()
+            .observe::<u32>(entity, move |_, _| {
+                let _ = &captured;
+            })
+            .forget();
+        ecs.update(std::time::Duration::ZERO);
+
+        em.destroy_entity(entity);
+        assert_eq!(Rc::strong_count(&observer_state), 2);
+        ecs.update(std::time::Duration::ZERO);
+
+        assert_eq!(Rc::strong_count(&observer_state), 1);
     }
 }
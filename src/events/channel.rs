@@ -0,0 +1,210 @@
+use std::{cell::Cell, marker::PhantomData};
+
+use crate::{ResMut, ResourceError, Resources};
+
+/// A channel of events of type `T`, stored as a resource and registered with
+/// `EntityComponentSystem::add_event`. Events are sent with an `EventWriter` and read with an
+/// `EventReader`.
+///
+/// Events live for two frames: the ones sent during a frame are kept until the end of the next
+/// one. Each reader sees every event once, whether it runs before or after the writer in the
+/// frame, as long as it reads at least once per frame.
+pub struct Events<T> {
+    // The events sent during the previous frame, then the ones sent during the current frame.
+    previous: Vec<T>,
+    current: Vec<T>,
+    // The id of the first event of each buffer. Ids increase with each event sent.
+    previous_start: usize,
+    current_start: usize,
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self { previous: Vec::new(), current: Vec::new(), previous_start: 0, current_start: 0 }
+    }
+}
+
+impl<T> Events<T> {
+    pub fn send(&mut self, event: T) {
+        self.current.push(event);
+    }
+
+    /// Drops the events sent during the previous frame, and keeps the ones sent during the current
+    /// frame for one more frame. Called by `EntityComponentSystem::update` at the start of every
+    /// frame.
+    pub fn update(&mut self) {
+        self.previous_start = self.current_start;
+        self.current_start += self.current.len();
+        self.previous = std::mem::take(&mut self.current);
+    }
+
+    /// The number of events in the channel, sent during the previous frame and the current one.
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // The id of the next event sent.
+    fn end(&self) -> usize {
+        self.current_start + self.current.len()
+    }
+
+    // The events from the id `start`, in the order they were sent.
+    fn events_from(&self, start: usize) -> impl Iterator<Item = &T> {
+        let previous = start
+            .saturating_sub(self.previous_start)
+            .min(self.previous.len());
+        let current = start
+            .saturating_sub(self.current_start)
+            .min(self.current.len());
+        self.previous[previous..]
+            .iter()
+            .chain(self.current[current..].iter())
+    }
+}
+
+/// Sends events of type `T`, borrowing the `Events<T>` resource mutably until it is dropped.
+///
+/// ```ignore
+/// let res = resources.borrow();
+/// EventWriter::<CollisionEvent>::new(&res)?.send(CollisionEvent { entity_a, entity_b });
+/// ```
+pub struct EventWriter<'a, T: 'static> {
+    events: ResMut<'a, Events<T>>,
+}
+
+impl<'a, T: 'static> EventWriter<'a, T> {
+    /// Fails if `add_event` wasn't called for `T`, or if the channel is borrowed.
+    pub fn new(resources: &'a Resources) -> Result<Self, ResourceError> {
+        Ok(Self { events: resources.get_mut::<Events<T>>()? })
+    }
+
+    pub fn send(&mut self, event: T) {
+        self.events.send(event);
+    }
+}
+
+/// Reads the events of type `T` not read yet by this reader. Each reader keeps its own cursor, so
+/// several systems can read the same events. A system usually owns its reader:
+///
+/// ```ignore
+/// struct DamageSystem {
+///     collisions: EventReader<CollisionEvent>,
+/// }
+///
+/// let res = resources.borrow();
+/// let events = res.get::<Events<CollisionEvent>>()?;
+/// for collision in self.collisions.read(&events) {
+///     // ...
+/// }
+/// ```
+///
+/// Events dropped before the reader reads them, because it didn't read for more than a frame, are
+/// skipped.
+pub struct EventReader<T> {
+    // The id of the next event to read.
+    cursor: Cell<usize>,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for EventReader<T> {
+    fn default() -> Self {
+        Self { cursor: Cell::new(0), phantom: PhantomData }
+    }
+}
+
+impl<T> EventReader<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The events sent since the last call, in the order they were sent.
+    pub fn read<'a>(&self, events: &'a Events<T>) -> impl Iterator<Item = &'a T> {
+        let start = self.cursor.replace(events.end());
+        events.events_from(start)
+    }
+
+    /// Returns `true` if there are events the reader hasn't read yet.
+    pub fn has_unread(&self, events: &Events<T>) -> bool {
+        events.events_from(self.cursor.get()).next().is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(reader: &EventReader<u32>, events: &Events<u32>) -> Vec<u32> {
+        reader.read(events).copied().collect()
+    }
+
+    #[test]
+    fn reader_running_before_the_writer_sees_events_in_the_next_frame() {
+        let mut events = Events::default();
+        let reader = EventReader::new();
+
+        events.update();
+        assert!(read(&reader, &events).is_empty());
+        events.send(1);
+
+        events.update();
+        assert_eq!(read(&reader, &events), vec![1]);
+        events.send(2);
+
+        events.update();
+        assert_eq!(read(&reader, &events), vec![2]);
+    }
+
+    #[test]
+    fn reader_running_after_the_writer_sees_events_in_the_same_frame() {
+        let mut events = Events::default();
+        let reader = EventReader::new();
+
+        events.update();
+        events.send(1);
+        assert_eq!(read(&reader, &events), vec![1]);
+
+        events.update();
+        events.send(2);
+        assert_eq!(read(&reader, &events), vec![2]);
+        assert!(!reader.has_unread(&events));
+    }
+
+    #[test]
+    fn events_are_dropped_after_two_updates() {
+        let mut events = Events::default();
+        events.send(1);
+
+        events.update();
+        assert_eq!(events.len(), 1);
+        assert_eq!(read(&EventReader::new(), &events), vec![1]);
+
+        events.update();
+        assert!(events.is_empty());
+        assert!(read(&EventReader::new(), &events).is_empty());
+    }
+
+    #[test]
+    fn reader_skipping_a_frame_gets_the_events_still_kept_once() {
+        let mut events = Events::default();
+        let reader = EventReader::new();
+
+        events.update();
+        events.send(1);
+        assert_eq!(read(&reader, &events), vec![1]);
+        events.send(2);
+
+        // The reader doesn't run in this frame.
+        events.update();
+        events.send(3);
+
+        events.update();
+        events.send(4);
+        // 2 was dropped by the update, while 3 and 4 are still kept.
+        assert_eq!(read(&reader, &events), vec![3, 4]);
+        assert!(read(&reader, &events).is_empty());
+    }
+}
@@ -4,82 +4,136 @@ mod entity_manager;
 pub mod events;
 mod resources;
 pub mod systems;
+mod transform;
 
 pub mod derive {
-    pub use macros::Component;
+    pub use macros::{Bundle, Component};
 }
 
 use std::{
-    cell::{Ref, RefCell, RefMut},
+    any::TypeId,
+    cell::{Cell, Ref, RefCell, RefMut},
+    collections::HashMap,
     rc::Rc,
     time::Duration,
 };
 
 pub use asset_manager::AssetManager;
 pub use component_signature::ComponentSignature;
-pub use entity_manager::{get_next_component_type_id, Component, Entity, EntityManager};
-use events::EventBus;
-pub use resources::Resources;
+pub use entity_manager::{
+    get_next_component_type_id, Added, AnyOf, Bundle, Changed, Children, Commands, Component,
+    ComponentRef, ComponentTicks, DeferredWorld, Entity, EntityBuilder, EntityId, EntityManager,
+    Generation, GroupManager, Mut, Parent, Prefab, Query, QueryFilter, QueryParam, Relation,
+    RelationCleanup, RemovedComponents, SpawnError, TagManager, Tick, With, Without,
+};
+// Named by the code generated by `#[derive(Bundle)]`.
+#[doc(hidden)]
+pub use entity_manager::Archetype;
+use entity_manager::EntityManagerInner;
+use events::{EventBus, Events};
+pub use resources::{Res, ResMut, ResourceError, Resources};
 use systems::System;
+pub use transform::{GlobalTransform, Transform, TransformPropagationSystem};
 
 pub struct EntityComponentSystem {
     entity_manager: EntityManager,
     systems: Vec<Rc<RefCell<Box<dyn System>>>>,
+    // The tick of the previous run of each system, in the same order as `systems`.
+    system_last_runs: Vec<Cell<Tick>>,
     asset_manager: AssetManager,
     event_bus: Rc<RefCell<EventBus>>,
     resources: Rc<RefCell<Resources>>,
+    // Updates the `Events` channels registered with `add_event`, by event type.
+    event_updates: HashMap<TypeId, fn(&Resources)>,
+    max_command_rounds: usize,
 }
 
 impl EntityComponentSystem {
+    pub const DEFAULT_MAX_COMMAND_ROUNDS: usize = 8;
+
     pub fn new() -> Self {
+        let entity_manager = EntityManager::new();
+        let resources = entity_manager.inner.borrow().resources.clone();
         EntityComponentSystem {
-            entity_manager: entity_manager::EntityManager::new(),
+            entity_manager,
             systems: Vec::new(),
+            system_last_runs: Vec::new(),
             asset_manager: AssetManager::default(),
             event_bus: Rc::new(RefCell::new(EventBus::default())),
-            resources: Rc::new(RefCell::new(Resources::default())),
+            resources,
+            event_updates: HashMap::new(),
+            max_command_rounds: Self::DEFAULT_MAX_COMMAND_ROUNDS,
         }
     }
 
+    /// Sets how many times the commands recorded while applying commands, such as the commands
+    /// of component hooks, are applied in a row at each sync point. Commands recorded past this
+    /// are dropped, to break infinite loops.
+    pub fn set_max_command_rounds(&mut self, rounds: usize) {
+        self.max_command_rounds = rounds;
+    }
+
+    /// Adds an `Events<T>` channel to the resources, unless there is already one, and updates it
+    /// at the start of every frame so its events live for two frames.
+    pub fn add_event<T: 'static>(&mut self) {
+        self.resources.borrow_mut().init_resource::<Events<T>>();
+        self.event_updates.insert(TypeId::of::<T>(), |resources| {
+            if let Ok(mut events) = resources.get_mut::<Events<T>>() {
+                events.update();
+            }
+        });
+    }
+
+    /// Adds the system, run after the systems added before it. The system is subscribed to the
+    /// events of its `get_event_type` with its `get_event_priority`.
     pub fn add_system<T: System + 'static>(&mut self, system: T) {
         let boxed: Rc<RefCell<Box<dyn System>>> = Rc::new(RefCell::new(Box::new(system)));
+        {
+            let system = boxed.borrow();
+            let event_bus = self.event_bus.borrow();
+            for type_id in system.get_event_type() {
+                event_bus.subscribe_type_with_priority(
+                    *type_id,
+                    boxed.clone(),
+                    system.get_event_priority(),
+                );
+            }
+        }
         self.systems.push(boxed);
+        self.system_last_runs.push(Cell::new(0));
     }
 
     pub fn update(&self, delta_time: Duration) {
         {
             let mut em = self.entity_manager.inner.borrow_mut();
-            {
-                for entity in em.entities_to_spawn.iter() {
-                    let entity_signature = em.get_signature(*entity).unwrap();
-                    for system in &self.systems {
-                        let mut system = system.borrow_mut();
-                        if system.signature().is_subset(entity_signature) {
-                            system.add_entity(*entity);
-                        }
-                    }
-                }
-            }
-
-            for entity in em.entities_to_despawn.iter() {
-                for system in &self.systems {
-                    let mut system = system.borrow_mut();
-                    system.remove_entity(*entity);
-                }
+            for entity in em.entities_to_spawn.iter() {
+                self.update_system_membership(*entity, &em);
             }
 
             em.update();
         }
-        self.event_bus.borrow_mut().clear();
-
-        for system in &self.systems {
-            for type_id in system.borrow().get_event_type() {
-                let mut eb = self.event_bus.borrow_mut();
-                eb.subscribe_type(*type_id, system.clone());
+        // Commands recorded outside of systems, e.g. by event listeners or while setting up the
+        // world, are applied once the frame has started, so their despawns are part of it.
+        // Applying them also updates the system membership of the entities despawned above.
+        self.apply_commands();
+        self.event_bus
+            .borrow()
+            .remove_dead_observers(&self.entity_manager);
+        {
+            let resources = self.resources.borrow();
+            for update in self.event_updates.values() {
+                update(&resources);
             }
         }
 
-        for system in &self.systems {
+        self.dispatch_queued_events();
+
+        for (system, last_run) in self.systems.iter().zip(&self.system_last_runs) {
+            let this_run = self
+                .entity_manager
+                .inner
+                .borrow_mut()
+                .begin_system_run(last_run.get());
             system.borrow().update(
                 delta_time,
                 &self.asset_manager,
@@ -87,31 +141,151 @@ impl EntityComponentSystem {
                 self.event_bus.clone(),
                 self.resources.clone(),
             );
+            self.entity_manager.inner.borrow_mut().end_system_run();
+            last_run.set(this_run);
+            self.apply_commands();
+            self.dispatch_queued_events();
         }
     }
 
-    pub fn create_entity(&mut self) -> Entity {
-        self.entity_manager.inner.borrow_mut().create_entity()
+    // Dispatches the events queued by the event bus in `DispatchMode::Queued`, then applies the
+    // commands recorded by the listeners.
+    fn dispatch_queued_events(&self) {
+        {
+            let event_bus = self.event_bus.borrow();
+            if event_bus.is_queue_empty() {
+                return;
+            }
+            event_bus.dispatch_queued(self.entity_manager.clone());
+        }
+        self.apply_commands();
     }
 
-    pub fn add_component<C: Component + 'static>(&mut self, entity: Entity, component: C) {
-        let mut em = self.entity_manager.inner.borrow_mut();
-        em.add_component(entity, component);
-        let signature = em.get_signature(entity).unwrap();
+    // Applies the commands recorded since the last sync point, including the ones recorded by
+    // component hooks while applying them, up to `max_command_rounds` rounds, then updates the
+    // system membership of every entity they touched.
+    fn apply_commands(&self) {
+        {
+            let mut em = self.entity_manager.inner.borrow_mut();
+            for _ in 0..self.max_command_rounds {
+                let commands = em.commands.take();
+                if commands.is_empty() {
+                    break;
+                }
+                for command in commands {
+                    command(&mut em);
+                }
+            }
 
-        for system in &mut self.systems {
-            if system.borrow().signature().is_subset(signature) {
-                system.borrow_mut().add_entity(entity);
+            let dropped = em.commands.take().len();
+            if dropped > 0 {
+                tracing::warn!(
+                    "dropped {dropped} commands recorded past the maximum of {} command rounds",
+                    self.max_command_rounds
+                );
             }
         }
+        self.update_touched_membership();
     }
 
-    pub fn remove_component<C: Component + 'static>(&mut self, entity: Entity) {
+    // Updates the system membership of the entities whose components changed, or that were
+    // despawned, since the last call. Entities waiting to be spawned are added to systems in the
+    // next update instead.
+    fn update_touched_membership(&self) {
         let mut em = self.entity_manager.inner.borrow_mut();
-        em.remove_component::<C>(entity);
+        for entity in em.take_touched() {
+            if em.entities_to_spawn.contains(&entity) {
+                continue;
+            }
+            match em.get_signature(entity) {
+                Some(_) => self.update_system_membership(entity, &em),
+                None => {
+                    for system in &self.systems {
+                        system.borrow_mut().remove_entity(entity);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn create_entity(&mut self) -> Entity {
+        self.entity_manager.inner.borrow_mut().create_entity()
+    }
+
+    /// Adds the Component `C` to the entity. Returns `false` if the entity is not alive.
+    pub fn add_component<C: Component + 'static>(&mut self, entity: Entity, component: C) -> bool {
+        if !self
+            .entity_manager
+            .inner
+            .borrow_mut()
+            .add_component(entity, component)
+        {
+            return false;
+        }
+        self.update_touched_membership();
+        true
+    }
+
+    /// Adds all the components of the bundle to the entity, updating its system membership once.
+    /// Returns `false` if the entity is not alive.
+    pub fn add_bundle<B: Bundle>(&mut self, entity: Entity, bundle: B) -> bool {
+        if !self
+            .entity_manager
+            .inner
+            .borrow_mut()
+            .add_bundle(entity, bundle)
+        {
+            return false;
+        }
+        self.update_touched_membership();
+        true
+    }
+
+    /// Returns an `EntityBuilder` creating an entity with all of its components, its tag and its
+    /// groups at once.
+    pub fn spawn(&mut self) -> EntityBuilder {
+        self.entity_manager.spawn()
+    }
+
+    /// Registers `prefab` under `name`, replacing the prefab previously registered with that name.
+    pub fn register_prefab(&mut self, name: &str, prefab: Prefab) {
+        self.entity_manager.register_prefab(name, prefab);
+    }
+
+    /// Returns an `EntityBuilder` creating an instance of the prefab named `name`, or `None` if no
+    /// prefab is registered with that name.
+    pub fn instantiate(&mut self, name: &str) -> Option<EntityBuilder> {
+        self.entity_manager.instantiate(name)
+    }
+
+    /// Removes the Component `C` from the entity. Returns `false` if the entity is not alive.
+    pub fn remove_component<C: Component + 'static>(&mut self, entity: Entity) -> bool {
+        if !self
+            .entity_manager
+            .inner
+            .borrow_mut()
+            .remove_component::<C>(entity)
+        {
+            return false;
+        }
+        self.update_touched_membership();
+        true
+    }
+
+    // Adds the entity to the systems whose signature it matches, and removes it from the others.
+    // Adding or removing a component, a tag or a group can do both, as system signatures may
+    // exclude them.
+    fn update_system_membership(&self, entity: Entity, em: &EntityManagerInner) {
         let signature = em.get_signature(entity).unwrap();
-        for system in &mut self.systems {
-            if !system.borrow().signature().is_subset(signature) {
+        for system in &self.systems {
+            let matches = {
+                let system = system.borrow();
+                system.signature().matches(signature)
+                    && em.matches_tags_and_groups(system.signature(), entity)
+            };
+            if matches {
+                system.borrow_mut().add_entity(entity);
+            } else {
                 system.borrow_mut().remove_entity(entity);
             }
         }
@@ -125,11 +299,11 @@ impl EntityComponentSystem {
         &mut self.asset_manager
     }
 
-    pub fn resources(&self) -> Ref<Resources> {
+    pub fn resources(&self) -> Ref<'_, Resources> {
         self.resources.borrow()
     }
 
-    pub fn resources_mut(&self) -> RefMut<Resources> {
+    pub fn resources_mut(&self) -> RefMut<'_, Resources> {
         self.resources.borrow_mut()
     }
 
@@ -147,3 +321,129 @@ impl Default for EntityComponentSystem {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entity_manager::impl_component, events::EventListener};
+
+    struct Value;
+    struct Frozen;
+    impl_component!(Value, Frozen);
+
+    // Tracks the entities the system is given, and runs `run` with the entity manager on each
+    // update.
+    struct TestSystem {
+        signature: ComponentSignature,
+        entities: Rc<RefCell<Vec<Entity>>>,
+        run: Box<dyn Fn(&EntityManager)>,
+    }
+
+    impl TestSystem {
+        fn new(signature: ComponentSignature) -> Self {
+            Self { signature, entities: Default::default(), run: Box::new(|_| {}) }
+        }
+    }
+
+    impl EventListener for TestSystem {}
+
+    impl System for TestSystem {
+        fn signature(&self) -> &ComponentSignature {
+            &self.signature
+        }
+
+        fn add_entity(&mut self, entity: Entity) {
+            if !self.entities.borrow().contains(&entity) {
+                self.entities.borrow_mut().push(entity);
+            }
+        }
+
+        fn remove_entity(&mut self, entity: Entity) {
+            self.entities.borrow_mut().retain(|e| *e != entity);
+        }
+
+        fn update(
+            &self,
+            _delta_time: Duration,
+            _asset_manager: &AssetManager,
+            em: EntityManager,
+            _event_bus: Rc<RefCell<EventBus>>,
+            _resources: Rc<RefCell<Resources>>,
+        ) {
+            (self.run)(&em);
+        }
+    }
+
+    #[test]
+    fn changes_made_inside_systems_update_membership() {
+        let mut ecs = EntityComponentSystem::new();
+        let em = ecs.entity_manager();
+        let entity = em.create_entity();
+        ecs.update(Duration::ZERO);
+
+        // The change made to the entity by the first system in the next update.
+        type Step = fn(&EntityManager, Entity);
+        let next_step: Rc<Cell<Option<Step>>> = Default::default();
+        let mut changer = TestSystem::new(ComponentSignature::default());
+        let changer_step = next_step.clone();
+        changer.run = Box::new(move |em| {
+            if let Some(step) = changer_step.take() {
+                step(em, entity);
+            }
+        });
+        ecs.add_system(changer);
+
+        let mut signature = ComponentSignature::default();
+        signature.require_component::<Value>();
+        signature.exclude_component::<Frozen>();
+        let valued = TestSystem::new(signature);
+        let valued_entities = valued.entities.clone();
+        ecs.add_system(valued);
+
+        let mut signature = ComponentSignature::default();
+        signature.require_tag("player");
+        signature.require_group("allies");
+        let tagged = TestSystem::new(signature);
+        let tagged_entities = tagged.entities.clone();
+        ecs.add_system(tagged);
+
+        let run = |step: Step| {
+            next_step.set(Some(step));
+            ecs.update(Duration::ZERO);
+            (
+                valued_entities.borrow().contains(&entity),
+                tagged_entities.borrow().contains(&entity),
+            )
+        };
+        assert_eq!(
+            run(|em, e| assert!(em.add_component(e, Value))),
+            (true, false)
+        );
+        assert_eq!(
+            run(|em, e| assert!(em.add_component(e, Frozen))),
+            (false, false)
+        );
+        assert_eq!(
+            run(|em, e| assert!(em.remove_component::<Frozen>(e))),
+            (true, false)
+        );
+        assert_eq!(run(|em, e| assert!(em.set_tag(e, "player"))), (true, false));
+        assert_eq!(
+            run(|em, e| assert!(em.add_entity_to_group(&e, "allies"))),
+            (true, true)
+        );
+        assert_eq!(run(|em, e| assert!(em.remove_tag(e))), (true, false));
+        assert_eq!(
+            run(|em, e| assert!(em.add_label(e, "player"))),
+            (true, true)
+        );
+        assert_eq!(
+            run(|em, e| assert!(em.remove_entity_from_group(&e, "allies"))),
+            (true, false)
+        );
+        assert_eq!(
+            run(|em, e| assert!(em.remove_component::<Value>(e))),
+            (false, false)
+        );
+    }
+}
@@ -1,27 +1,137 @@
 use std::{
-    any::{Any, TypeId},
+    any::{type_name, Any, TypeId},
+    cell::{Ref, RefCell, RefMut},
     collections::HashMap,
+    fmt,
+    ops::{Deref, DerefMut},
 };
 
+/// The global values shared by the systems, such as the camera or the dimensions of the map, with
+/// at most one resource per type.
+///
+/// Each resource is stored in its own cell, so a system can read one resource while writing
+/// another:
+///
+/// ```ignore
+/// let res = resources.borrow();
+/// let map_dimensions = res.get::<MapDimensions>()?;
+/// let mut camera = res.get_mut::<Camera>()?;
+/// ```
+///
+/// Borrowing a resource that is already borrowed mutably, or borrowing mutably a resource that is
+/// already borrowed, returns an error instead of panicking.
 #[derive(Default)]
 pub struct Resources {
-    resources: HashMap<TypeId, Box<dyn Any>>,
+    resources: HashMap<TypeId, RefCell<Box<dyn Any>>>,
+}
+
+/// Why a resource couldn't be borrowed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResourceError {
+    /// No resource of the type was inserted.
+    Missing { type_name: &'static str },
+    /// The resource is borrowed in a way that conflicts with the requested borrow.
+    AlreadyBorrowed { type_name: &'static str },
+}
+
+impl fmt::Display for ResourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing { type_name } => write!(f, "no resource of type {type_name}"),
+            Self::AlreadyBorrowed { type_name } => {
+                write!(f, "the resource of type {type_name} is already borrowed")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ResourceError {}
+
+/// A shared borrow of the resource of type `T`, returned by `Resources::get`.
+pub struct Res<'a, T>(Ref<'a, T>);
+
+impl<T> Deref for Res<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// A mutable borrow of the resource of type `T`, returned by `Resources::get_mut`.
+pub struct ResMut<'a, T>(RefMut<'a, T>);
+
+impl<T> Deref for ResMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for ResMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
 }
 
 impl Resources {
+    /// Inserts the resource, replacing the resource of the same type.
     pub fn put<T: Any>(&mut self, resource: T) {
-        self.resources.insert(TypeId::of::<T>(), Box::new(resource));
+        self.resources
+            .insert(TypeId::of::<T>(), RefCell::new(Box::new(resource)));
     }
 
-    pub fn get<T: Any>(&self) -> Option<&T> {
-        self.resources
+    /// Inserts the default value of `T`, unless there is already a resource of that type.
+    pub fn init_resource<T: Any + Default>(&mut self) {
+        self.get_or_insert_with(T::default);
+    }
+
+    /// Returns the resource of type `T`, inserting the value returned by `f` if there is none.
+    pub fn get_or_insert_with<T: Any>(&mut self, f: impl FnOnce() -> T) -> &mut T {
+        let resource = self
+            .resources
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| RefCell::new(Box::new(f())));
+        // Resources are stored under the TypeId of their type.
+        resource.get_mut().downcast_mut::<T>().unwrap()
+    }
+
+    /// Removes the resource of type `T` and returns it.
+    pub fn remove<T: Any>(&mut self) -> Option<T> {
+        let resource = self.resources.remove(&TypeId::of::<T>())?;
+        resource.into_inner().downcast::<T>().ok().map(|r| *r)
+    }
+
+    pub fn contains<T: Any>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Borrows the resource of type `T`. Fails if there is none, or if it is borrowed mutably.
+    pub fn get<T: Any>(&self) -> Result<Res<'_, T>, ResourceError> {
+        let resource = self
+            .resources
             .get(&TypeId::of::<T>())
-            .map(|r| r.downcast_ref::<T>().unwrap())
+            .ok_or(ResourceError::Missing { type_name: type_name::<T>() })?;
+        let resource = resource
+            .try_borrow()
+            .map_err(|_| ResourceError::AlreadyBorrowed { type_name: type_name::<T>() })?;
+        Ref::filter_map(resource, |r| r.downcast_ref::<T>())
+            .map(Res)
+            .map_err(|_| ResourceError::Missing { type_name: type_name::<T>() })
     }
 
-    pub fn get_mut<T: Any>(&mut self) -> Option<&mut T> {
-        self.resources
-            .get_mut(&TypeId::of::<T>())
-            .map(|r| r.downcast_mut::<T>().unwrap())
+    /// Borrows the resource of type `T` mutably. Fails if there is none, or if it is borrowed.
+    pub fn get_mut<T: Any>(&self) -> Result<ResMut<'_, T>, ResourceError> {
+        let resource = self
+            .resources
+            .get(&TypeId::of::<T>())
+            .ok_or(ResourceError::Missing { type_name: type_name::<T>() })?;
+        let resource = resource
+            .try_borrow_mut()
+            .map_err(|_| ResourceError::AlreadyBorrowed { type_name: type_name::<T>() })?;
+        RefMut::filter_map(resource, |r| r.downcast_mut::<T>())
+            .map(ResMut)
+            .map_err(|_| ResourceError::Missing { type_name: type_name::<T>() })
     }
 }
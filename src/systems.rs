@@ -13,6 +13,11 @@ pub trait System: EventListener {
     fn get_event_type(&self) -> &[TypeId] {
         &[]
     }
+    /// The priority of the system as a listener of the events of `get_event_type`. Listeners with
+    /// a higher priority receive the events first.
+    fn get_event_priority(&self) -> i32 {
+        0
+    }
     /// The update function is called for every frame.
     fn update(
         &self,
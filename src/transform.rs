@@ -0,0 +1,295 @@
+use std::{cell::RefCell, collections::HashSet, rc::Rc, time::Duration};
+
+use macroquad::math::{Affine2, Vec2};
+
+use crate::{
+    component_signature::ComponentSignature,
+    entity_manager::impl_component,
+    events::{EventBus, EventListener},
+    systems::System,
+    Added, AssetManager, Changed, Entity, EntityManager, Parent, Resources, Without,
+};
+
+/// The translation, rotation and scale of an entity, relative to its parent, or to the world for
+/// entities without a parent.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec2,
+    /// The rotation in radians.
+    pub rotation: f32,
+    pub scale: Vec2,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self { translation: Vec2::ZERO, rotation: 0.0, scale: Vec2::ONE };
+
+    pub fn from_translation(translation: Vec2) -> Self {
+        Self { translation, ..Self::IDENTITY }
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vec2) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// The affine transformation applying the scale, then the rotation, then the translation.
+    pub fn compute_affine(&self) -> Affine2 {
+        Affine2::from_scale_angle_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+/// The transform of an entity in world space, combining its `Transform` with the transforms of
+/// its ancestors. Computed by the `TransformPropagationSystem`, which also adds it to the entities
+/// with a `Transform`, so it shouldn't be modified directly.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GlobalTransform(Affine2);
+
+impl Default for GlobalTransform {
+    fn default() -> Self {
+        Self(Affine2::IDENTITY)
+    }
+}
+
+impl GlobalTransform {
+    pub fn affine(&self) -> Affine2 {
+        self.0
+    }
+
+    pub fn translation(&self) -> Vec2 {
+        self.0.translation
+    }
+
+    /// The rotation in radians.
+    pub fn rotation(&self) -> f32 {
+        self.0.to_scale_angle_translation().1
+    }
+
+    pub fn scale(&self) -> Vec2 {
+        self.0.to_scale_angle_translation().0
+    }
+
+    /// Transforms a point from the local space of the entity to world space.
+    pub fn transform_point(&self, point: Vec2) -> Vec2 {
+        self.0.transform_point2(point)
+    }
+}
+
+impl_component!(Transform, GlobalTransform);
+
+/// Computes the `GlobalTransform` of every entity with a `Transform`. Only the subtrees where a
+/// `Transform` or a `Parent` changed since the previous run are walked, from their topmost changed
+/// entity down, so the system does no work in frames where nothing moved.
+///
+/// Children without a `Transform` are skipped along with their descendants. The system should be
+/// added before the systems reading `GlobalTransform`, as the values are only up to date once it
+/// has run.
+pub struct TransformPropagationSystem {
+    signature: ComponentSignature,
+}
+
+impl Default for TransformPropagationSystem {
+    fn default() -> Self {
+        let mut signature = ComponentSignature::default();
+        signature.require_component::<Transform>();
+        Self { signature }
+    }
+}
+
+impl TransformPropagationSystem {
+    // The entities whose global transform must be computed again, along with their descendants.
+    fn dirty_entities(entity_manager: &EntityManager) -> HashSet<Entity> {
+        let mut dirty = HashSet::new();
+        dirty.extend(
+            entity_manager
+                .query_filtered::<&Transform, Changed<Transform>>()
+                .entities(),
+        );
+        dirty.extend(
+            entity_manager
+                .query_filtered::<&GlobalTransform, Added<GlobalTransform>>()
+                .entities(),
+        );
+        dirty.extend(
+            entity_manager
+                .query_filtered::<&Parent, Changed<Parent>>()
+                .entities(),
+        );
+        // Entities detached from their parent become roots.
+        dirty.extend(entity_manager.removed::<Parent>().entities());
+        dirty
+    }
+
+    // The global transform of the parent of a dirty entity, or `None` if the entity is computed
+    // along with a dirty ancestor, or is skipped as one of its ancestors has no `Transform`.
+    fn parent_affine(
+        entity_manager: &EntityManager,
+        entity: Entity,
+        dirty: &HashSet<Entity>,
+    ) -> Option<Affine2> {
+        let ancestors = entity_manager.ancestors(entity);
+        for ancestor in &ancestors {
+            if dirty.contains(ancestor)
+                || entity_manager
+                    .get_component::<Transform>(ancestor)
+                    .is_none()
+            {
+                return None;
+            }
+        }
+        match ancestors.first() {
+            Some(parent) => entity_manager
+                .get_component::<GlobalTransform>(parent)
+                .map(|global| global.borrow().0),
+            None => Some(Affine2::IDENTITY),
+        }
+    }
+}
+
+impl EventListener for TransformPropagationSystem {}
+
+impl System for TransformPropagationSystem {
+    fn signature(&self) -> &ComponentSignature {
+        &self.signature
+    }
+
+    fn add_entity(&mut self, _entity: Entity) {}
+
+    fn remove_entity(&mut self, _entity: Entity) {}
+
+    fn update(
+        &self,
+        _delta_time: Duration,
+        _asset_manager: &AssetManager,
+        entity_manager: EntityManager,
+        _event_bus: Rc<RefCell<EventBus>>,
+        _resources: Rc<RefCell<Resources>>,
+    ) {
+        let missing = entity_manager
+            .query_filtered::<&Transform, Without<GlobalTransform>>()
+            .entities();
+        for entity in missing {
+            entity_manager.add_component(entity, GlobalTransform::default());
+        }
+
+        let dirty = Self::dirty_entities(&entity_manager);
+        if dirty.is_empty() {
+            return;
+        }
+
+        // Entities to compute, with the global transform of their parent, starting from the
+        // topmost dirty entities.
+        let mut stack: Vec<(Entity, Affine2)> = dirty
+            .iter()
+            .filter_map(|entity| {
+                let parent_affine = Self::parent_affine(&entity_manager, *entity, &dirty)?;
+                Some((*entity, parent_affine))
+            })
+            .collect();
+        while let Some((entity, parent_affine)) = stack.pop() {
+            let Some(transform) = entity_manager.get_component::<Transform>(&entity) else {
+                continue;
+            };
+            let Some(global) = entity_manager.get_component::<GlobalTransform>(&entity) else {
+                continue;
+            };
+
+            let affine = parent_affine * transform.borrow().compute_affine();
+            // Only write actual changes, so systems filtering on `Changed<GlobalTransform>` skip
+            // the entities that were computed again without moving.
+            if global.borrow().0 != affine {
+                global.borrow_mut().0 = affine;
+            }
+            for child in entity_manager.children(entity) {
+                stack.push((child, affine));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EntityComponentSystem;
+
+    fn translation(entity_manager: &EntityManager, entity: Entity) -> Vec2 {
+        entity_manager
+            .get_component::<GlobalTransform>(&entity)
+            .unwrap()
+            .borrow()
+            .translation()
+    }
+
+    #[test]
+    fn propagates_changes_down_the_hierarchy() {
+        let mut ecs = EntityComponentSystem::new();
+        ecs.add_system(TransformPropagationSystem::default());
+        let em = ecs.entity_manager();
+        let root = em
+            .spawn()
+            .with(Transform::from_translation(Vec2::new(10.0, 0.0)))
+            .id();
+        let child = em
+            .spawn()
+            .with(Transform::from_translation(Vec2::new(1.0, 0.0)))
+            .id();
+        let grandchild = em
+            .spawn()
+            .with(Transform::from_translation(Vec2::new(0.0, 1.0)))
+            .id();
+        em.set_parent(child, root);
+        em.set_parent(grandchild, child);
+        ecs.update(Duration::ZERO);
+        assert_eq!(translation(&em, grandchild), Vec2::new(11.0, 1.0));
+
+        em.get_component::<Transform>(&child)
+            .unwrap()
+            .borrow_mut()
+            .translation = Vec2::ZERO;
+        ecs.update(Duration::ZERO);
+        assert_eq!(translation(&em, root), Vec2::new(10.0, 0.0));
+        assert_eq!(translation(&em, child), Vec2::new(10.0, 0.0));
+        assert_eq!(translation(&em, grandchild), Vec2::new(10.0, 1.0));
+
+        em.remove_parent(child);
+        ecs.update(Duration::ZERO);
+        assert_eq!(translation(&em, grandchild), Vec2::new(0.0, 1.0));
+    }
+
+    #[test]
+    fn leaves_clean_subtrees_untouched() {
+        let mut ecs = EntityComponentSystem::new();
+        ecs.add_system(TransformPropagationSystem::default());
+        let em = ecs.entity_manager();
+        let moving = em.spawn().with(Transform::IDENTITY).id();
+        let still = em.spawn().with(Transform::IDENTITY).id();
+        ecs.update(Duration::ZERO);
+        let changed = |entity| {
+            em.get_component::<GlobalTransform>(&entity)
+                .unwrap()
+                .ticks()
+                .changed
+        };
+        let still_changed = changed(still);
+
+        em.get_component::<Transform>(&moving)
+            .unwrap()
+            .borrow_mut()
+            .translation = Vec2::ONE;
+        ecs.update(Duration::ZERO);
+        ecs.update(Duration::ZERO);
+
+        assert_eq!(translation(&em, moving), Vec2::ONE);
+        assert_eq!(changed(still), still_changed);
+    }
+}